@@ -0,0 +1,123 @@
+use super::cartridge::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// ~1 second of emulated time without RAM writes.
+const IDLE_FLUSH_CYCLES: u64 = 4_194_304;
+// ~1 minute of emulated time while the game keeps writing.
+const PERIODIC_FLUSH_CYCLES: u64 = 60 * IDLE_FLUSH_CYCLES;
+
+// Persists the battery backed cartridge RAM in a raw .sav file next to the ROM.
+pub struct BatterySave {
+  path: PathBuf,
+  is_dirty: bool,
+  last_write_cycles: u64,
+  last_flush_cycles: u64,
+}
+
+impl BatterySave {
+  pub fn new(rom_path: &Path) -> BatterySave {
+    BatterySave {
+      path: rom_path.with_extension("sav"),
+      is_dirty: false,
+      last_write_cycles: 0,
+      last_flush_cycles: 0,
+    }
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  pub fn load(&self, cartridge: &mut Cartridge) {
+    match fs::read(&self.path) {
+      Ok(data) => {
+        info!("Save file loaded: {}", self.path.display());
+        cartridge.load_battery_data(&data);
+      }
+      Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+        info!("No save file found at {}", self.path.display());
+      }
+      Err(err) => warn!("Cannot read save file {}: {}", self.path.display(), err),
+    }
+  }
+
+  // Flushes after a period without writes, or periodically when the game writes continuously.
+  pub fn update(&mut self, cartridge: &mut Cartridge, cycles: u64) {
+    if cartridge.take_ram_dirty() {
+      if !self.is_dirty {
+        self.last_flush_cycles = cycles;
+      }
+      self.is_dirty = true;
+      self.last_write_cycles = cycles;
+    }
+
+    if !self.is_dirty {
+      return;
+    }
+
+    if cycles - self.last_write_cycles >= IDLE_FLUSH_CYCLES
+      || cycles - self.last_flush_cycles >= PERIODIC_FLUSH_CYCLES
+    {
+      self.flush(cartridge, cycles);
+    }
+  }
+
+  pub fn flush(&mut self, cartridge: &Cartridge, cycles: u64) {
+    match self.write(&cartridge.battery_data()) {
+      Ok(_) => debug!("Save file written: {}", self.path.display()),
+      Err(err) => warn!("Cannot write save file {}: {}", self.path.display(), err),
+    }
+
+    self.is_dirty = false;
+    self.last_flush_cycles = cycles;
+  }
+
+  // Writes a temporary file first so a crash mid-write does not corrupt the existing save.
+  fn write(&self, data: &[u8]) -> io::Result<()> {
+    let tmp_path = self.path.with_extension("sav.tmp");
+    fs::write(&tmp_path, data)?;
+    fs::rename(&tmp_path, &self.path)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use std::env;
+
+  fn make_cartridge() -> Cartridge {
+    let mut rom = vec![0; 0x8000];
+    // MBC1 + RAM + battery, 8K RAM.
+    rom[0x0147] = 0x03;
+    rom[0x0149] = 0x02;
+    Cartridge::from_bytes(rom).unwrap()
+  }
+
+  #[test]
+  fn test_flush_after_idle_period() {
+    let rom_path = env::temp_dir().join("yagbe_battery_test.gb");
+    let mut save = BatterySave::new(&rom_path);
+    let _ = fs::remove_file(save.path());
+
+    let mut cartridge = make_cartridge();
+    cartridge.write_word(0x0000, 0x0a).unwrap();
+    cartridge.write_word(0xa001, 0x42).unwrap();
+
+    save.update(&mut cartridge, 100);
+    assert!(!save.path().exists());
+
+    save.update(&mut cartridge, 100 + IDLE_FLUSH_CYCLES);
+    let data = fs::read(save.path()).unwrap();
+    assert_eq!(0x2000, data.len());
+    assert_eq!(0x42, data[1]);
+
+    let mut other = make_cartridge();
+    save.load(&mut other);
+    other.write_word(0x0000, 0x0a).unwrap();
+    assert_eq!(0x42, other.read_word(0xa001).unwrap());
+
+    let _ = fs::remove_file(save.path());
+  }
+}
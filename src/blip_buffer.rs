@@ -0,0 +1,182 @@
+use std::f64::consts::PI;
+
+// Taps of the band-limited step, the output lags the input by half of it.
+const KERNEL_WIDTH: usize = 16;
+// Step positions between two output samples.
+const KERNEL_PHASES: usize = 32;
+// Of the output sample rate, just below the Nyquist frequency.
+const CUTOFF: f64 = 0.45;
+
+// Band-limited resampler of a signal given as amplitude changes (deltas) at input clock times. Every delta adds a
+// windowed sinc impulse to the output, read as the running sum of the impulses, so the steps don't alias.
+pub struct BlipBuffer {
+  // Output samples per input clock.
+  ratio: f64,
+  // Output position of the clock 0 of the current frame, the samples before it are complete.
+  frame_start: f64,
+  // Impulses of the output samples not read yet.
+  deltas: Vec<f32>,
+  // Sum of the deltas read.
+  amplitude: f32,
+  // A second of output, the oldest samples are dropped when nobody reads them.
+  max_samples: usize,
+  kernel: Vec<[f32; KERNEL_WIDTH]>,
+}
+
+impl BlipBuffer {
+  pub fn new(clock_rate: f64, sample_rate: f64) -> BlipBuffer {
+    BlipBuffer {
+      ratio: sample_rate / clock_rate,
+      frame_start: 0.0,
+      deltas: vec![],
+      amplitude: 0.0,
+      max_samples: sample_rate as usize,
+      kernel: (0..KERNEL_PHASES).map(kernel_phase).collect(),
+    }
+  }
+
+  pub fn set_rates(&mut self, clock_rate: f64, sample_rate: f64) {
+    self.ratio = sample_rate / clock_rate;
+    self.max_samples = sample_rate as usize;
+  }
+
+  // Changes the amplitude by `delta` at the given clock of the current frame.
+  pub fn add_delta(&mut self, clock: u64, delta: f32) {
+    let position = self.frame_start + clock as f64 * self.ratio;
+    let index = position as usize;
+    let phase = ((position - index as f64) * KERNEL_PHASES as f64) as usize;
+
+    let end = index + KERNEL_WIDTH;
+    if self.deltas.len() < end {
+      self.deltas.resize(end, 0.0);
+    }
+
+    let taps = self.kernel[phase].iter();
+    for (delta_sum, tap) in self.deltas[index..end].iter_mut().zip(taps) {
+      *delta_sum += delta * tap;
+    }
+  }
+
+  // Ends the current frame after the given clocks, the next frame starts at clock 0.
+  pub fn end_frame(&mut self, clocks: u64) {
+    self.frame_start += clocks as f64 * self.ratio;
+
+    let excess = self.samples_available().saturating_sub(self.max_samples);
+    if excess > 0 {
+      self.skip_samples(excess);
+    }
+  }
+
+  pub fn samples_available(&self) -> usize {
+    self.frame_start as usize
+  }
+
+  // The complete samples.
+  pub fn read_samples(&mut self) -> Vec<f32> {
+    let count = self.samples_available();
+    if self.deltas.len() < count {
+      self.deltas.resize(count, 0.0);
+    }
+    self.frame_start -= count as f64;
+
+    let amplitude = &mut self.amplitude;
+    self
+      .deltas
+      .drain(..count)
+      .map(|delta| {
+        *amplitude += delta;
+        *amplitude
+      })
+      .collect()
+  }
+
+  fn skip_samples(&mut self, count: usize) {
+    if self.deltas.len() < count {
+      self.deltas.resize(count, 0.0);
+    }
+    self.frame_start -= count as f64;
+
+    self.amplitude = self
+      .deltas
+      .drain(..count)
+      .fold(self.amplitude, |amplitude, delta| amplitude + delta);
+  }
+}
+
+// Blackman windowed sinc impulse, centered at half the width plus the phase, normalized to a step of 1.
+fn kernel_phase(phase: usize) -> [f32; KERNEL_WIDTH] {
+  let center = (KERNEL_WIDTH / 2) as f64 + phase as f64 / KERNEL_PHASES as f64;
+
+  let mut taps = [0.0; KERNEL_WIDTH];
+  for (i, tap) in taps.iter_mut().enumerate() {
+    let x = i as f64 - center;
+    if x.abs() > (KERNEL_WIDTH / 2) as f64 {
+      continue;
+    }
+
+    let window = 0.42
+      + 0.5 * (2.0 * PI * x / KERNEL_WIDTH as f64).cos()
+      + 0.08 * (4.0 * PI * x / KERNEL_WIDTH as f64).cos();
+    *tap = (sinc(2.0 * CUTOFF * x) * window) as f32;
+  }
+
+  let sum: f32 = taps.iter().sum();
+  for tap in taps.iter_mut() {
+    *tap /= sum;
+  }
+  taps
+}
+
+fn sinc(x: f64) -> f64 {
+  if x == 0.0 {
+    1.0
+  } else {
+    (PI * x).sin() / (PI * x)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_sample_count() {
+    let mut blip = BlipBuffer::new(4_194_304.0, 44_100.0);
+    let mut count = 0;
+    for _ in 0..100 {
+      blip.end_frame(70224);
+      count += blip.read_samples().len();
+    }
+    // 100 frames of 70224 clocks, 7022400 / 4194304 * 44100 = 73835.7
+    assert_eq!(73835, count);
+  }
+
+  #[test]
+  fn test_step() {
+    let mut blip = BlipBuffer::new(1000.0, 100.0);
+    blip.add_delta(15, 0.5);
+    blip.end_frame(1000);
+
+    let samples = blip.read_samples();
+    assert_eq!(100, samples.len());
+    assert!(samples[..2].iter().all(|&sample| sample == 0.0));
+    // Settled after the kernel width.
+    assert!(samples[KERNEL_WIDTH + 2..]
+      .iter()
+      .all(|&sample| (sample - 0.5).abs() < 1e-6));
+    assert!(samples.iter().any(|&sample| sample > 0.5));
+  }
+
+  #[test]
+  fn test_max_samples() {
+    let mut blip = BlipBuffer::new(1000.0, 100.0);
+    blip.add_delta(15, 0.5);
+    blip.end_frame(3000);
+    assert_eq!(100, blip.samples_available());
+
+    // The oldest samples are dropped, the step is kept.
+    let samples = blip.read_samples();
+    assert_eq!(100, samples.len());
+    assert!(samples.iter().all(|&sample| (sample - 0.5).abs() < 1e-6));
+  }
+}
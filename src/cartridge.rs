@@ -0,0 +1,496 @@
+use super::mbc1::*;
+use super::mbc2::*;
+use super::mbc3::*;
+use super::mbc5::*;
+use super::mem::UnmappedAddress;
+use std::error::Error;
+use std::fmt;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+const HEADER_END: usize = 0x0150;
+
+#[derive(Debug)]
+pub enum CartridgeError {
+  TooSmall(usize),
+  UnknownCartridgeType(u8),
+  UnsupportedCartridgeType(MbcKind),
+  InvalidRomSize(u8),
+  InvalidRamSize(u8),
+}
+
+impl fmt::Display for CartridgeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CartridgeError::TooSmall(len) => write!(
+        f,
+        "ROM image is too small to contain a header: {} bytes",
+        len
+      ),
+      CartridgeError::UnknownCartridgeType(code) => {
+        write!(f, "Unknown cartridge type: 0x{:>02x}", code)
+      }
+      CartridgeError::UnsupportedCartridgeType(kind) => {
+        write!(f, "Unsupported cartridge type: {:?}", kind)
+      }
+      CartridgeError::InvalidRomSize(code) => write!(f, "Invalid ROM size code: 0x{:>02x}", code),
+      CartridgeError::InvalidRamSize(code) => write!(f, "Invalid RAM size code: 0x{:>02x}", code),
+    }
+  }
+}
+
+impl Error for CartridgeError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MbcKind {
+  RomOnly,
+  Mbc1,
+  Mbc2,
+  Mbc3,
+  Mbc5,
+  Mbc6,
+  Mbc7,
+  Mmm01,
+  PocketCamera,
+  Tama5,
+  HuC3,
+  HuC1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CartridgeType {
+  pub code: u8,
+  pub mbc: MbcKind,
+  pub has_ram: bool,
+  pub has_battery: bool,
+  pub has_timer: bool,
+  pub has_rumble: bool,
+}
+
+impl CartridgeType {
+  pub fn from_code(code: u8) -> Option<CartridgeType> {
+    // (MBC, RAM, battery, timer, rumble)
+    let (mbc, has_ram, has_battery, has_timer, has_rumble) = match code {
+      0x00 => (MbcKind::RomOnly, false, false, false, false),
+      0x01 => (MbcKind::Mbc1, false, false, false, false),
+      0x02 => (MbcKind::Mbc1, true, false, false, false),
+      0x03 => (MbcKind::Mbc1, true, true, false, false),
+      0x05 => (MbcKind::Mbc2, false, false, false, false),
+      0x06 => (MbcKind::Mbc2, false, true, false, false),
+      0x08 => (MbcKind::RomOnly, true, false, false, false),
+      0x09 => (MbcKind::RomOnly, true, true, false, false),
+      0x0b => (MbcKind::Mmm01, false, false, false, false),
+      0x0c => (MbcKind::Mmm01, true, false, false, false),
+      0x0d => (MbcKind::Mmm01, true, true, false, false),
+      0x0f => (MbcKind::Mbc3, false, true, true, false),
+      0x10 => (MbcKind::Mbc3, true, true, true, false),
+      0x11 => (MbcKind::Mbc3, false, false, false, false),
+      0x12 => (MbcKind::Mbc3, true, false, false, false),
+      0x13 => (MbcKind::Mbc3, true, true, false, false),
+      0x19 => (MbcKind::Mbc5, false, false, false, false),
+      0x1a => (MbcKind::Mbc5, true, false, false, false),
+      0x1b => (MbcKind::Mbc5, true, true, false, false),
+      0x1c => (MbcKind::Mbc5, false, false, false, true),
+      0x1d => (MbcKind::Mbc5, true, false, false, true),
+      0x1e => (MbcKind::Mbc5, true, true, false, true),
+      0x20 => (MbcKind::Mbc6, true, true, false, false),
+      0x22 => (MbcKind::Mbc7, true, true, false, true),
+      0xfc => (MbcKind::PocketCamera, true, true, false, false),
+      0xfd => (MbcKind::Tama5, true, true, true, false),
+      0xfe => (MbcKind::HuC3, true, true, true, false),
+      0xff => (MbcKind::HuC1, true, true, false, false),
+      _ => return None,
+    };
+
+    Some(CartridgeType {
+      code,
+      mbc,
+      has_ram,
+      has_battery,
+      has_timer,
+      has_rumble,
+    })
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgbFlag {
+  DmgOnly,
+  CgbCompatible,
+  CgbOnly,
+}
+
+#[derive(Debug, Clone)]
+pub struct CartridgeHeader {
+  pub title: String,
+  pub manufacturer_code: Option<String>,
+  pub cgb_flag: CgbFlag,
+  pub new_licensee_code: String,
+  pub sgb_flag: bool,
+  pub cartridge_type: CartridgeType,
+  pub rom_size: usize,
+  pub ram_size: usize,
+  pub is_japanese: bool,
+  pub old_licensee_code: u8,
+  pub version: u8,
+  pub header_checksum: u8,
+  pub global_checksum: u16,
+}
+
+impl CartridgeHeader {
+  pub fn parse(rom: &[u8]) -> Result<CartridgeHeader, CartridgeError> {
+    if rom.len() < HEADER_END {
+      return Err(CartridgeError::TooSmall(rom.len()));
+    }
+
+    let cgb_flag = match rom[0x0143] {
+      0x80 => CgbFlag::CgbCompatible,
+      0xc0 => CgbFlag::CgbOnly,
+      _ => CgbFlag::DmgOnly,
+    };
+
+    // Newer cartridges shortened the title to make space for the manufacturer code and the CGB flag.
+    let manufacturer_code = if cgb_flag != CgbFlag::DmgOnly
+      && rom[0x013f..0x0143]
+        .iter()
+        .all(|&b| (b as char).is_ascii_uppercase() || (b as char).is_ascii_digit())
+    {
+      Some(String::from_utf8_lossy(&rom[0x013f..0x0143]).into_owned())
+    } else {
+      None
+    };
+    let title_end = match (cgb_flag, &manufacturer_code) {
+      (_, Some(_)) => 0x013f,
+      (CgbFlag::DmgOnly, None) => 0x0144,
+      (_, None) => 0x0143,
+    };
+    let title = rom[0x0134..title_end]
+      .iter()
+      .take_while(|&&b| b != 0x0)
+      .map(|&b| if b.is_ascii_graphic() { b as char } else { ' ' })
+      .collect::<String>()
+      .trim_end()
+      .to_owned();
+
+    let cartridge_type = CartridgeType::from_code(rom[0x0147])
+      .ok_or(CartridgeError::UnknownCartridgeType(rom[0x0147]))?;
+
+    let rom_size = match rom[0x0148] {
+      code @ 0x00...0x08 => (32 * 1024) << code,
+      code => return Err(CartridgeError::InvalidRomSize(code)),
+    };
+
+    let ram_size = match rom[0x0149] {
+      0x00 => 0,
+      // Unofficial, listed in various docs.
+      0x01 => 2 * 1024,
+      0x02 => 8 * 1024,
+      0x03 => 32 * 1024,
+      0x04 => 128 * 1024,
+      0x05 => 64 * 1024,
+      code => return Err(CartridgeError::InvalidRamSize(code)),
+    };
+
+    Ok(CartridgeHeader {
+      title,
+      manufacturer_code,
+      cgb_flag,
+      new_licensee_code: String::from_utf8_lossy(&rom[0x0144..0x0146]).into_owned(),
+      sgb_flag: rom[0x0146] == 0x03,
+      cartridge_type,
+      rom_size,
+      ram_size,
+      is_japanese: rom[0x014a] == 0x00,
+      old_licensee_code: rom[0x014b],
+      version: rom[0x014c],
+      header_checksum: rom[0x014d],
+      global_checksum: dword!(rom[0x014e], rom[0x014f]),
+    })
+  }
+
+  // The old licensee code 0x33 means the new licensee code has to be used.
+  pub fn licensee(&self) -> String {
+    if self.old_licensee_code == 0x33 {
+      self.new_licensee_code.clone()
+    } else {
+      format!("{:>02X}", self.old_licensee_code)
+    }
+  }
+
+  pub fn calculate_header_checksum(rom: &[u8]) -> u8 {
+    rom[0x0134..=0x014c]
+      .iter()
+      .fold(0u8, |acc, &b| acc.wrapping_sub(b).wrapping_sub(1))
+  }
+
+  pub fn calculate_global_checksum(rom: &[u8]) -> u16 {
+    rom
+      .iter()
+      .enumerate()
+      .filter(|&(i, _)| i != 0x014e && i != 0x014f)
+      .fold(0u16, |acc, (_, &b)| acc.wrapping_add(b as u16))
+  }
+}
+
+// Side effects of the cartridge hardware that the frontend might want to act on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CartridgeEvent {
+  Rumble(bool),
+}
+
+pub trait MemoryBankController {
+  // 0x0000 - 0x7fff.
+  fn read_rom(&self, addr: u16) -> u8;
+  // Writes to the ROM area are setting the controller registers.
+  fn write_rom(&mut self, addr: u16, w: u8);
+  // 0xa000 - 0xbfff.
+  fn read_ram(&self, addr: u16) -> u8;
+  fn write_ram(&mut self, addr: u16, w: u8);
+  // Battery backed state, the external RAM followed by any controller specific data (eg. RTC).
+  fn battery_data(&self) -> Vec<u8>;
+  fn load_battery_data(&mut self, data: &[u8]);
+  // Events generated since the last call.
+  fn drain_events(&mut self) -> Vec<CartridgeEvent> {
+    vec![]
+  }
+}
+
+// Reads from a banked memory, out of range banks are wrapped around like the unconnected address lines would do.
+pub fn read_banked(data: &[u8], bank_size: usize, bank: usize, addr: u16) -> u8 {
+  if data.is_empty() {
+    return 0xff;
+  }
+
+  let offs = (bank * bank_size + (addr as usize & (bank_size - 1))) % data.len();
+  data[offs]
+}
+
+pub fn write_banked(data: &mut [u8], bank_size: usize, bank: usize, addr: u16, w: u8) {
+  if data.is_empty() {
+    return;
+  }
+
+  let offs = (bank * bank_size + (addr as usize & (bank_size - 1))) % data.len();
+  data[offs] = w;
+}
+
+pub fn load_ram(ram: &mut [u8], data: &[u8]) {
+  if data.len() < ram.len() {
+    warn!(
+      "Save data is smaller ({} bytes) than the cartridge RAM ({} bytes).",
+      data.len(),
+      ram.len()
+    );
+  }
+
+  let len = ram.len().min(data.len());
+  ram[..len].copy_from_slice(&data[..len]);
+}
+
+// Simple 32K cartridge, optionally with up to 8K of RAM.
+pub struct RomOnly {
+  rom: Vec<u8>,
+  ram: Vec<u8>,
+}
+
+impl RomOnly {
+  pub fn new(rom: Vec<u8>, ram_size: usize) -> RomOnly {
+    RomOnly {
+      rom,
+      ram: vec![0; ram_size],
+    }
+  }
+}
+
+impl MemoryBankController for RomOnly {
+  fn read_rom(&self, addr: u16) -> u8 {
+    read_banked(&self.rom, 0x8000, 0, addr)
+  }
+
+  fn write_rom(&mut self, addr: u16, w: u8) {
+    debug!(
+      "Write to ROM only cartridge is ignored: 0x{:>04x} = 0x{:>02x}",
+      addr, w
+    );
+  }
+
+  fn read_ram(&self, addr: u16) -> u8 {
+    read_banked(&self.ram, RAM_BANK_SIZE, 0, addr)
+  }
+
+  fn write_ram(&mut self, addr: u16, w: u8) {
+    write_banked(&mut self.ram, RAM_BANK_SIZE, 0, addr, w);
+  }
+
+  fn battery_data(&self) -> Vec<u8> {
+    self.ram.clone()
+  }
+
+  fn load_battery_data(&mut self, data: &[u8]) {
+    load_ram(&mut self.ram, data);
+  }
+}
+
+pub struct Cartridge {
+  pub header: CartridgeHeader,
+  mbc: Box<dyn MemoryBankController>,
+  // Set on external RAM writes, cleared when the save file picks it up.
+  is_ram_dirty: bool,
+}
+
+impl Cartridge {
+  pub fn from_bytes(mut rom: Vec<u8>) -> Result<Cartridge, CartridgeError> {
+    let header = CartridgeHeader::parse(&rom)?;
+
+    if CartridgeHeader::calculate_header_checksum(&rom) != header.header_checksum {
+      warn!("Cartridge header checksum mismatch, image might be corrupt.");
+    }
+    if CartridgeHeader::calculate_global_checksum(&rom) != header.global_checksum {
+      warn!("Cartridge global checksum mismatch.");
+    }
+    if rom.len() != header.rom_size {
+      warn!(
+        "ROM image size ({} bytes) does not match the header ({} bytes).",
+        rom.len(),
+        header.rom_size
+      );
+      if rom.len() < header.rom_size {
+        rom.resize(header.rom_size, 0xff);
+      }
+    }
+
+    let ram_size = if header.cartridge_type.has_ram {
+      header.ram_size
+    } else {
+      0
+    };
+
+    let mbc: Box<dyn MemoryBankController> = match header.cartridge_type.mbc {
+      MbcKind::RomOnly => Box::new(RomOnly::new(rom, ram_size)),
+      MbcKind::Mbc1 => Box::new(Mbc1::new(rom, ram_size)),
+      MbcKind::Mbc2 => Box::new(Mbc2::new(rom)),
+      MbcKind::Mbc3 => Box::new(Mbc3::new(rom, ram_size, header.cartridge_type.has_timer)),
+      MbcKind::Mbc5 => Box::new(Mbc5::new(rom, ram_size, header.cartridge_type.has_rumble)),
+      kind => return Err(CartridgeError::UnsupportedCartridgeType(kind)),
+    };
+
+    info!("Cartridge loaded: {:#?}", header);
+
+    Ok(Cartridge {
+      header,
+      mbc,
+      is_ram_dirty: false,
+    })
+  }
+
+  pub fn read_word(&self, addr: u16) -> Result<u8, UnmappedAddress> {
+    match addr {
+      0x0000...0x7fff => Ok(self.mbc.read_rom(addr)),
+      0xa000...0xbfff => Ok(self.mbc.read_ram(addr)),
+      _ => Err(UnmappedAddress(addr)),
+    }
+  }
+
+  pub fn write_word(&mut self, addr: u16, w: u8) -> Result<(), UnmappedAddress> {
+    match addr {
+      0x0000...0x7fff => self.mbc.write_rom(addr, w),
+      0xa000...0xbfff => {
+        self.mbc.write_ram(addr, w);
+        self.is_ram_dirty = true;
+      }
+      _ => return Err(UnmappedAddress(addr)),
+    }
+    Ok(())
+  }
+
+  pub fn has_battery(&self) -> bool {
+    self.header.cartridge_type.has_battery
+  }
+
+  pub fn take_ram_dirty(&mut self) -> bool {
+    let is_ram_dirty = self.is_ram_dirty;
+    self.is_ram_dirty = false;
+    is_ram_dirty
+  }
+
+  pub fn battery_data(&self) -> Vec<u8> {
+    self.mbc.battery_data()
+  }
+
+  pub fn load_battery_data(&mut self, data: &[u8]) {
+    self.mbc.load_battery_data(data);
+  }
+
+  pub fn drain_events(&mut self) -> Vec<CartridgeEvent> {
+    self.mbc.drain_events()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn make_rom(cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+    let mut rom = vec![0; (32 * 1024) << rom_size];
+    rom[0x0134..0x0139].copy_from_slice(b"TETRA");
+    rom[0x0147] = cartridge_type;
+    rom[0x0148] = rom_size;
+    rom[0x0149] = ram_size;
+    rom[0x014b] = 0x01;
+    rom[0x014d] = CartridgeHeader::calculate_header_checksum(&rom);
+    let global_checksum = CartridgeHeader::calculate_global_checksum(&rom);
+    rom[0x014e] = (global_checksum >> 8) as u8;
+    rom[0x014f] = global_checksum as u8;
+    rom
+  }
+
+  #[test]
+  fn test_parse_header() {
+    let rom = make_rom(0x03, 0x02, 0x03);
+    let header = CartridgeHeader::parse(&rom).unwrap();
+
+    assert_eq!("TETRA", header.title);
+    assert_eq!(None, header.manufacturer_code);
+    assert_eq!(CgbFlag::DmgOnly, header.cgb_flag);
+    assert_eq!(MbcKind::Mbc1, header.cartridge_type.mbc);
+    assert!(header.cartridge_type.has_battery);
+    assert_eq!(128 * 1024, header.rom_size);
+    assert_eq!(32 * 1024, header.ram_size);
+    assert_eq!("01", header.licensee());
+    assert_eq!(
+      CartridgeHeader::calculate_header_checksum(&rom),
+      header.header_checksum
+    );
+  }
+
+  #[test]
+  fn test_parse_cgb_header() {
+    let mut rom = make_rom(0x00, 0x00, 0x00);
+    rom[0x013f..0x0143].copy_from_slice(b"ABCE");
+    rom[0x0143] = 0x80;
+    let header = CartridgeHeader::parse(&rom).unwrap();
+
+    assert_eq!(Some("ABCE".to_owned()), header.manufacturer_code);
+    assert_eq!(CgbFlag::CgbCompatible, header.cgb_flag);
+  }
+
+  #[test]
+  fn test_reject_corrupt_images() {
+    assert!(Cartridge::from_bytes(vec![0; 0x100]).is_err());
+    assert!(Cartridge::from_bytes(make_rom(0x42, 0x00, 0x00)).is_err());
+
+    let mut rom = make_rom(0x00, 0x00, 0x00);
+    rom[0x0148] = 0x42;
+    assert!(Cartridge::from_bytes(rom).is_err());
+  }
+
+  #[test]
+  fn test_truncated_image_is_padded() {
+    let mut rom = make_rom(0x01, 0x02, 0x00);
+    rom.truncate(0x5000);
+    let cartridge = Cartridge::from_bytes(rom).unwrap();
+
+    assert_eq!(0xff, cartridge.read_word(0x7fff).unwrap());
+  }
+}
@@ -0,0 +1,199 @@
+// Units shared by the sound channels, clocked by the frame sequencer.
+
+// Volume envelope of the pulse and noise channels (NRx2), clocked at 64 Hz.
+#[derive(Debug, Default)]
+pub struct Envelope {
+  reg: u8,
+  volume: u8,
+  timer: u8,
+}
+
+impl Envelope {
+  pub fn read(&self) -> u8 {
+    self.reg
+  }
+
+  pub fn write(&mut self, w: u8) {
+    self.reg = w;
+  }
+
+  // The upper 5 bits of NRx2 power the DAC of the channel.
+  pub fn is_dac_enabled(&self) -> bool {
+    self.reg & 0b1111_1000 != 0
+  }
+
+  pub fn volume(&self) -> u8 {
+    self.volume
+  }
+
+  pub fn trigger(&mut self) {
+    self.volume = self.reg >> 4;
+    self.timer = self.period();
+  }
+
+  pub fn clock(&mut self) {
+    if self.period() == 0 {
+      return;
+    }
+
+    // Triggered with a period of 0, the period was written after.
+    if self.timer == 0 {
+      self.timer = self.period();
+    }
+
+    self.timer -= 1;
+    if self.timer > 0 {
+      return;
+    }
+    self.timer = self.period();
+
+    if self.is_increasing() && self.volume < 0xf {
+      self.volume += 1;
+    } else if !self.is_increasing() && self.volume > 0 {
+      self.volume -= 1;
+    }
+  }
+
+  fn is_increasing(&self) -> bool {
+    bitn!(self.reg, 3) == 0x1
+  }
+
+  fn period(&self) -> u8 {
+    self.reg & 0b111
+  }
+}
+
+// Turns the channel off when it runs out, clocked at 256 Hz.
+#[derive(Debug, Clone, Copy)]
+pub struct LengthCounter {
+  // 64 for the pulse and noise, 256 for the wave channel.
+  max: u16,
+  counter: u16,
+  is_enabled: bool,
+}
+
+impl LengthCounter {
+  pub fn new(max: u16) -> LengthCounter {
+    LengthCounter {
+      max,
+      counter: 0,
+      is_enabled: false,
+    }
+  }
+
+  // NRx1 holds the length as `max - counter`.
+  pub fn load(&mut self, length: u8) {
+    self.counter = self.max - length as u16;
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.is_enabled
+  }
+
+  // Enabling the counter while the next frame sequencer step doesn't clock it gives it an extra clock.
+  // Returns true when that expires the counter.
+  pub fn set_enabled(&mut self, is_enabled: bool, is_clocked_next: bool) -> bool {
+    let was_enabled = self.is_enabled;
+    self.is_enabled = is_enabled;
+
+    !was_enabled && is_enabled && !is_clocked_next && self.clock()
+  }
+
+  // An expired counter is reloaded, minus the extra clock when enabled in the same situation as above.
+  pub fn trigger(&mut self, is_clocked_next: bool) {
+    if self.counter == 0 {
+      self.counter = self.max;
+      if self.is_enabled && !is_clocked_next {
+        self.counter -= 1;
+      }
+    }
+  }
+
+  // Returns true when the counter expired and the channel has to be turned off.
+  pub fn clock(&mut self) -> bool {
+    if !self.is_enabled || self.counter == 0 {
+      return false;
+    }
+
+    self.counter -= 1;
+    self.counter == 0
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_envelope() {
+    let mut envelope = Envelope::default();
+    // Volume 2, decreasing every 2nd clock.
+    envelope.write(0b0010_0010);
+    envelope.trigger();
+
+    envelope.clock();
+    assert_eq!(2, envelope.volume());
+    envelope.clock();
+    assert_eq!(1, envelope.volume());
+    for _ in 0..4 {
+      envelope.clock();
+    }
+    assert_eq!(0, envelope.volume());
+  }
+
+  #[test]
+  fn test_envelope_period_after_trigger() {
+    let mut envelope = Envelope::default();
+    envelope.write(0b0010_0000);
+    envelope.trigger();
+
+    // Decreasing every 2nd clock, from the write on.
+    envelope.write(0b0010_0010);
+    envelope.clock();
+    assert_eq!(2, envelope.volume());
+    envelope.clock();
+    assert_eq!(1, envelope.volume());
+  }
+
+  #[test]
+  fn test_length_counter() {
+    let mut length = LengthCounter::new(64);
+    length.load(62);
+    assert!(!length.clock());
+
+    length.set_enabled(true, true);
+    assert!(!length.clock());
+    assert!(length.clock());
+    assert!(!length.clock());
+
+    // Triggering with an expired counter reloads the full length.
+    length.trigger(true);
+    for _ in 0..63 {
+      assert!(!length.clock());
+    }
+    assert!(length.clock());
+  }
+
+  #[test]
+  fn test_length_counter_extra_clock() {
+    let mut length = LengthCounter::new(64);
+    length.load(63);
+    // Enabled in the first half of a length period, the extra clock expires the counter.
+    assert!(length.set_enabled(true, false));
+
+    // Re-enabling doesn't clock again.
+    length.load(62);
+    assert!(!length.set_enabled(true, false));
+    assert!(!length.clock());
+
+    // The reload on trigger loses the extra clock too.
+    length.set_enabled(false, false);
+    length.load(63);
+    assert!(length.set_enabled(true, false));
+    length.trigger(false);
+    for _ in 0..62 {
+      assert!(!length.clock());
+    }
+    assert!(length.clock());
+  }
+}
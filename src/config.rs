@@ -0,0 +1,139 @@
+use super::input::*;
+use super::palette::*;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+
+pub const DEFAULT_CONFIG_FILE: &str = "yagbe.cfg";
+
+#[derive(Debug)]
+pub enum ConfigError {
+  Io(io::Error),
+  // Line number (1 based) and the line.
+  InvalidLine(usize, String),
+  UnknownKey(usize, String),
+  InvalidValue(usize, String),
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ConfigError::Io(err) => write!(f, "Cannot read config file: {}", err),
+      ConfigError::InvalidLine(line, content) => {
+        write!(f, "Line {}: expected key = value, got '{}'", line, content)
+      }
+      ConfigError::UnknownKey(line, key) => write!(f, "Line {}: unknown key '{}'", line, key),
+      ConfigError::InvalidValue(line, msg) => write!(f, "Line {}: {}", line, msg),
+    }
+  }
+}
+
+impl Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+  fn from(err: io::Error) -> ConfigError {
+    ConfigError::Io(err)
+  }
+}
+
+// Simple `key = value` file, `#` starts a comment line.
+#[derive(Debug, Default)]
+pub struct Config {
+  pub palette: OutputPalette,
+  pub key_bindings: KeyBindings,
+  pub pad_config: PadConfig,
+}
+
+impl Config {
+  pub fn load(path: &str) -> Result<Config, ConfigError> {
+    Config::parse(&fs::read_to_string(path)?)
+  }
+
+  pub fn parse(content: &str) -> Result<Config, ConfigError> {
+    let mut config = Config::default();
+
+    for (i, line) in content.lines().enumerate() {
+      let line_number = i + 1;
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      let mut parts = line.splitn(2, '=');
+      let key = parts.next().unwrap().trim();
+      let value = parts
+        .next()
+        .ok_or_else(|| ConfigError::InvalidLine(line_number, line.to_owned()))?
+        .trim();
+
+      match key {
+        "palette" => {
+          config.palette = OutputPalette::parse(value)
+            .map_err(|err| ConfigError::InvalidValue(line_number, err.to_string()))?;
+        }
+        _ if key.starts_with("key_") => {
+          let button = Button::parse(&key["key_".len()..])
+            .ok_or_else(|| ConfigError::UnknownKey(line_number, key.to_owned()))?;
+          if value.is_empty() {
+            return Err(ConfigError::InvalidValue(
+              line_number,
+              format!("Missing key name for '{}'", key),
+            ));
+          }
+          config.key_bindings.set_key(button, value);
+        }
+        // `pad_SETTING` for all controllers, `pad_SETTING@CONTROLLER NAME` for one.
+        _ if key.starts_with("pad_") => {
+          let mut key_parts = key["pad_".len()..].splitn(2, '@');
+          let name = key_parts.next().unwrap().trim();
+          if name != "deadzone" && Button::parse(name).is_none() {
+            return Err(ConfigError::UnknownKey(line_number, key.to_owned()));
+          }
+
+          let setting = PadSetting::parse(name, value)
+            .map_err(|err| ConfigError::InvalidValue(line_number, err))?;
+          match key_parts.next() {
+            Some(controller_name) => config
+              .pad_config
+              .controller_settings
+              .push((controller_name.trim().to_owned(), setting)),
+            None => config.pad_config.bindings.apply(&setting),
+          }
+        }
+        _ => return Err(ConfigError::UnknownKey(line_number, key.to_owned())),
+      }
+    }
+
+    Ok(config)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_parse() {
+    let config = Config::parse("# Colors\n\npalette = pocket_grey\n").unwrap();
+    assert_eq!(OutputPalette::pocket_grey(), config.palette);
+
+    match Config::parse("palette = pocket_grey\nscale = 2") {
+      Err(ConfigError::UnknownKey(2, key)) => assert_eq!("scale", key),
+      other => panic!("Unexpected result: {:?}", other),
+    }
+    assert!(Config::parse("palette").is_err());
+
+    let config = Config::parse("key_start = Space\nkey_a = Left Shift").unwrap();
+    assert_eq!("Space", config.key_bindings.key(Button::Start));
+    assert_eq!("Left Shift", config.key_bindings.key(Button::A));
+    assert_eq!("Z", config.key_bindings.key(Button::B));
+    assert!(Config::parse("key_turbo = T").is_err());
+
+    let config = Config::parse("pad_deadzone = 0.1\npad_start@Pro Controller = plus").unwrap();
+    assert_eq!(0.1, config.pad_config.bindings.deadzone);
+    let bindings = config.pad_config.bindings_for("Pro Controller");
+    assert_eq!(&["plus".to_owned()], bindings.inputs(Button::Start));
+    assert!(Config::parse("pad_deadzone = much").is_err());
+  }
+}
@@ -1,6 +1,6 @@
 use super::util::*;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, Copy)]
 pub struct Cpu {
   pub reg_a: u8,
   pub reg_f: u8,
@@ -24,6 +24,22 @@ impl Cpu {
     self.pc = 0x0;
   }
 
+  // Registers as the DMG boot ROM leaves them when jumping to the cartridge entry point.
+  pub fn reset_post_boot(&mut self, header_checksum: u8) {
+    self.set_af(0x01b0);
+    self.set_bc(0x0013);
+    self.set_de(0x00d8);
+    self.set_hl(0x014d);
+    self.sp = 0xfffe;
+    self.pc = 0x0100;
+
+    // Half carry and carry are only set when the header checksum is not zero.
+    if header_checksum == 0x0 {
+      self.reset_flag_half_carry();
+      self.reset_flag_carry();
+    }
+  }
+
   pub fn reg_af(&self) -> u16 {
     dword!(self.reg_a, self.reg_f)
   }
@@ -68,15 +84,6 @@ impl Cpu {
   pub fn pc_inc(&mut self) -> u16 {
     let pc = self.pc;
     self.pc += 1;
-
-    if self.pc >= 0x8000 && (self.pc < 0xc000 || self.pc > 0xfffe) {
-      unimplemented!(
-        "PC is out of standard rom bank space: 0x{:>04x}\n{:#x?}",
-        self.pc,
-        self
-      );
-    }
-
     pc
   }
 
@@ -1,6 +1,9 @@
 use super::cpu::*;
 use super::emu::*;
 use super::graphics::*;
+use super::interrupt::*;
+use super::palette::*;
+use super::sdl_display::*;
 use super::util::*;
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
@@ -20,6 +23,7 @@ pub enum DebuggerCommand {
   Breakpoint,
   MemoryPrint(u16, usize),
   CpuPrint,
+  InterruptPrint,
   Quit,
   Display,
   PrintBackgroundMap,
@@ -46,8 +50,8 @@ pub struct Debugger {
 }
 
 impl Debugger {
-  pub fn new(sdl: Rc<Sdl>) -> Debugger {
-    let video_subsystem = sdl.video().unwrap();
+  pub fn new(sdl: Rc<Sdl>, start_pc: u16) -> Result<Debugger, SdlError> {
+    let video_subsystem = sdl.video()?;
     let background_debug_window = video_subsystem
       .window(
         "Y.A.G.B.E. BACKGROUND DEBUG",
@@ -56,8 +60,7 @@ impl Debugger {
       )
       .position(16 + 160 * Debugger::scale() as i32 + 16, 64)
       .opengl()
-      .build()
-      .unwrap();
+      .build()?;
 
     let tile_debug_window = video_subsystem
       .window(
@@ -67,24 +70,23 @@ impl Debugger {
       )
       .position(32 + 416 * Debugger::scale() as i32 + 16, 64)
       .opengl()
-      .build()
-      .unwrap();
+      .build()?;
 
     let mut debugger: Debugger = Debugger {
       breakpoints: HashSet::new(),
       next_count: None,
-      bg_debug_canvas: background_debug_window.into_canvas().build().unwrap(),
-      tile_debug_canvas: tile_debug_window.into_canvas().build().unwrap(),
-      ttf_context: ttf::init().unwrap(),
+      bg_debug_canvas: background_debug_window.into_canvas().build()?,
+      tile_debug_canvas: tile_debug_window.into_canvas().build()?,
+      ttf_context: ttf::init()?,
       pc_history: History::with_capacity(1024),
       debug_displays_on: true,
-      log: File::create("./debug.log").unwrap(),
+      log: File::create("./debug.log")?,
       log_on: false,
       print_cpu: false,
     };
     // Break at start.
-    debugger.breakpoints.insert(0x0);
-    debugger
+    debugger.breakpoints.insert(start_pc);
+    Ok(debugger)
   }
 
   pub fn should_break(&mut self, cpu: &Cpu) -> bool {
@@ -121,43 +123,40 @@ impl Debugger {
     print!("[YAGBE]> ");
     let _ = stdout().flush();
 
-    let _ = stdin().read_line(&mut buffer).unwrap();
+    // End of input (or unreadable input) leaves the emulator.
+    if stdin().read_line(&mut buffer).unwrap_or(0) == 0 {
+      return DebuggerCommand::Quit;
+    }
 
     let parts = buffer.trim().split(' ').collect::<Vec<&str>>();
 
     match parts[0] {
-      "next" | "n" => {
-        let n = if parts.len() > 1 {
-          usize::from_str_radix(parts[1], 10).unwrap()
-        } else {
-          1
-        };
-        self.next_count = Some(n);
-
-        DebuggerCommand::Next
-      }
+      "next" | "n" => match parse_arg_or(&parts, 1, 10, 1) {
+        Some(n) if n > 0 => {
+          self.next_count = Some(n);
+          DebuggerCommand::Next
+        }
+        _ => invalid_command("Usage: next [STEPS]"),
+      },
       "continue" | "c" | "run" => DebuggerCommand::Continue,
-      "breakpoint" | "break" | "b" => {
-        self
-          .breakpoints
-          .insert(u16::from_str_radix(parts[1], 16).unwrap());
-        DebuggerCommand::Breakpoint
-      }
-      "-breakpoint" | "-break" | "-b" => {
-        self
-          .breakpoints
-          .remove(&u16::from_str_radix(parts[1], 16).unwrap());
-        DebuggerCommand::Breakpoint
-      }
-      "memory" | "mem" | "m" => {
-        let addr = u16::from_str_radix(parts[1], 16).unwrap();
-        let len = if parts.len() > 2 {
-          usize::from_str_radix(parts[2], 10).unwrap()
-        } else {
-          1
-        };
-        DebuggerCommand::MemoryPrint(addr, len)
-      }
+      "breakpoint" | "break" | "b" => match parse_addr(&parts, 1) {
+        Some(addr) => {
+          self.breakpoints.insert(addr);
+          DebuggerCommand::Breakpoint
+        }
+        None => invalid_command("Usage: breakpoint ADDRESS_HEX"),
+      },
+      "-breakpoint" | "-break" | "-b" => match parse_addr(&parts, 1) {
+        Some(addr) => {
+          self.breakpoints.remove(&addr);
+          DebuggerCommand::Breakpoint
+        }
+        None => invalid_command("Usage: -breakpoint ADDRESS_HEX"),
+      },
+      "memory" | "mem" | "m" => match (parse_addr(&parts, 1), parse_arg_or(&parts, 2, 10, 1)) {
+        (Some(addr), Some(len)) => DebuggerCommand::MemoryPrint(addr, len),
+        _ => invalid_command("Usage: memory START_HEX [LENGTH]"),
+      },
       "backgroundmap" | "bgmap" | "bgm" => DebuggerCommand::PrintBackgroundMap,
       "background-on" | "bg-on" => {
         self.debug_displays_on = true;
@@ -168,6 +167,7 @@ impl Debugger {
         DebuggerCommand::BackgroundOff
       }
       "cpu" => DebuggerCommand::CpuPrint,
+      "interrupts" | "int" => DebuggerCommand::InterruptPrint,
       "history" | "h" => DebuggerCommand::History,
       "display" | "d" => DebuggerCommand::Display,
       "log-on" | "lon" => {
@@ -195,8 +195,14 @@ impl Debugger {
     }
   }
 
-  pub fn update_debug_windows(&mut self, iteration_count: u64, cpu: &Cpu, graphics: &Graphics) {
-    self.update_debug_background_window(iteration_count, cpu, graphics);
+  pub fn update_debug_windows(
+    &mut self,
+    iteration_count: u64,
+    cpu: &Cpu,
+    graphics: &Graphics,
+    interrupts: &InterruptController,
+  ) {
+    self.update_debug_background_window(iteration_count, cpu, graphics, interrupts);
     self.update_debug_tile_window(graphics);
   }
 
@@ -205,6 +211,7 @@ impl Debugger {
     iteration_count: u64,
     cpu: &Cpu,
     graphics: &Graphics,
+    interrupts: &InterruptController,
   ) {
     if !self.debug_displays_on {
       return;
@@ -238,8 +245,8 @@ impl Debugger {
             let color_bit_hi = (graphics.vmem[tile_addr + (iy * 2)] >> (7 - ix)) & 1;
             let color_bit_lo = (graphics.vmem[tile_addr + (iy * 2) + 1] >> (7 - ix)) & 1;
             let color_code = (color_bit_hi << 1) | color_bit_lo;
-            let color = graphics.color_bit_to_color(color_code);
-            self.bg_debug_canvas.set_draw_color(color.as_sdl_color());
+            let color = sdl_color(OutputPalette::default().color(color_code));
+            self.bg_debug_canvas.set_draw_color(color);
             let _ = self.bg_debug_canvas.fill_rect(Rect::new(
               ((orig_x + ix) * Debugger::scale()) as i32,
               ((orig_y + iy) * Debugger::scale()) as i32,
@@ -288,6 +295,23 @@ impl Debugger {
     );
     self.render_text(format!("SP: 0x{:0>4x} PC: 0x{:0>4x}", cpu.sp, cpu.pc), 80);
     self.render_text(format!("LCDC 0b{:0>8b}", graphics.lcdc), 96);
+    self.render_text(
+      format!(
+        "IE 0b{:0>5b} IF 0b{:0>5b} IME {:?}",
+        interrupts.enabled(),
+        interrupts.requested(),
+        interrupts.is_master_enabled()
+      ),
+      112,
+    );
+    self.render_text(
+      format!(
+        "Pending {:?} Last {:?}",
+        interrupts.next_pending(),
+        interrupts.last_serviced()
+      ),
+      128,
+    );
 
     self.bg_debug_canvas.present();
   }
@@ -313,8 +337,8 @@ impl Debugger {
             let color_bit_hi = (graphics.vmem[tile_addr + (iy * 2)] >> (7 - ix)) & 1;
             let color_bit_lo = (graphics.vmem[tile_addr + (iy * 2) + 1] >> (7 - ix)) & 1;
             let color_code = (color_bit_hi << 1) | color_bit_lo;
-            let color = graphics.color_bit_to_color(color_code);
-            self.tile_debug_canvas.set_draw_color(color.as_sdl_color());
+            let color = sdl_color(OutputPalette::default().color(color_code));
+            self.tile_debug_canvas.set_draw_color(color);
             let _ = self.tile_debug_canvas.fill_rect(Rect::new(
               ((orig_x + ix) * Debugger::scale()) as i32,
               ((orig_y + iy) * Debugger::scale()) as i32,
@@ -329,31 +353,32 @@ impl Debugger {
     self.tile_debug_canvas.present();
   }
 
+  // A missing font only leaves the text out.
   fn render_text(&mut self, text: String, offs_y: i32) {
-    let mut font = self
-      .ttf_context
-      .load_font("asset/DroidSansMono.ttf", 12)
-      .unwrap();
+    if let Err(err) = self.try_render_text(text, offs_y) {
+      warn!("Cannot render debugger text: {}", err);
+    }
+  }
+
+  fn try_render_text(&mut self, text: String, offs_y: i32) -> Result<(), String> {
+    let mut font = self.ttf_context.load_font("asset/DroidSansMono.ttf", 12)?;
     font.set_style(ttf::FontStyle::BOLD);
 
     let surface = font
       .render(text.as_ref())
       .solid(Color::RGB(64, 96, 192))
-      .unwrap();
+      .map_err(|err| err.to_string())?;
 
     let texture_creator = self.bg_debug_canvas.texture_creator();
     let texture = texture_creator
       .create_texture_from_surface(surface)
-      .unwrap();
+      .map_err(|err| err.to_string())?;
 
     let sdl2::render::TextureQuery { width, height, .. } = texture.query();
 
     let target = sdl2::rect::Rect::new(4, offs_y, width, height);
 
-    self
-      .bg_debug_canvas
-      .copy(&texture, None, Some(target))
-      .unwrap();
+    self.bg_debug_canvas.copy(&texture, None, Some(target))
   }
 
   pub fn print_history(&self) {
@@ -372,3 +397,22 @@ impl Debugger {
     )
   }
 }
+
+fn invalid_command(usage: &str) -> DebuggerCommand {
+  println!("{}", usage);
+  DebuggerCommand::Invalid
+}
+
+// The argument at the given position, the default when it's missing, None when it can't be parsed.
+fn parse_arg_or(parts: &[&str], index: usize, radix: u32, default: usize) -> Option<usize> {
+  match parts.get(index) {
+    Some(part) => usize::from_str_radix(part, radix).ok(),
+    None => Some(default),
+  }
+}
+
+fn parse_addr(parts: &[&str], index: usize) -> Option<u16> {
+  parts
+    .get(index)
+    .and_then(|part| u16::from_str_radix(part, 16).ok())
+}
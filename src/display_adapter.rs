@@ -1,21 +1,37 @@
+use super::palette::*;
 use std::fmt;
 
-pub trait PixelDrawer {
-  fn clear(&mut self);
-  fn pixel(&mut self, x: usize, y: usize);
-  fn draw(&self);
-  fn on(&mut self);
-  fn off(&mut self);
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+// One byte per pixel, row major, holding the shade (0 - white ... 3 - black) after the palette registers.
+pub type Framebuffer = [u8; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+// Receives the finished frames of the PPU.
+pub trait VideoSink {
+  // Called on every VBlank (and once per frame period while the LCD is off).
+  fn frame_ready(&mut self, framebuffer: &Framebuffer);
+  fn set_output_palette(&mut self, _output_palette: OutputPalette) {}
+}
+
+// Converts a frame of shades to RGBA bytes.
+pub fn framebuffer_to_rgba(framebuffer: &Framebuffer, output_palette: &OutputPalette) -> Vec<u8> {
+  let mut rgba = Vec::with_capacity(framebuffer.len() * 4);
+  for &shade in framebuffer.iter() {
+    let rgb = output_palette.color(shade);
+    rgba.extend_from_slice(&[rgb.r, rgb.g, rgb.b, 0xff]);
+  }
+  rgba
 }
 
 pub struct ConsoleDisplay {
-  buffer: [bool; 160 * 144],
+  buffer: Framebuffer,
 }
 
 impl Default for ConsoleDisplay {
   fn default() -> Self {
     ConsoleDisplay {
-      buffer: [false; 160 * 144],
+      buffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
     }
   }
 }
@@ -26,31 +42,22 @@ impl fmt::Debug for ConsoleDisplay {
   }
 }
 
-impl PixelDrawer for ConsoleDisplay {
-  fn clear(&mut self) {
-    self.buffer = [false; 160 * 144];
-  }
-
-  fn pixel(&mut self, x: usize, y: usize) {
-    if x >= 160 || y >= 144 {
-      return;
-    }
-
-    self.buffer[y * 160 + x] = true;
-  }
-
-  fn draw(&self) {
-    for y in 0..144 {
+impl ConsoleDisplay {
+  pub fn draw(&self) {
+    for y in 0..SCREEN_HEIGHT {
       println!(
         "{:?}",
-        self.buffer[(y * 160)..(y * 160 + 144)]
+        self.buffer[(y * SCREEN_WIDTH)..((y + 1) * SCREEN_WIDTH)]
           .iter()
-          .map(|&v| if v { 'X' } else { '_' })
+          .map(|&shade| if shade != 0 { 'X' } else { '_' })
           .collect::<String>()
       );
     }
   }
+}
 
-  fn on(&mut self) {}
-  fn off(&mut self) {}
+impl VideoSink for ConsoleDisplay {
+  fn frame_ready(&mut self, framebuffer: &Framebuffer) {
+    self.buffer.copy_from_slice(framebuffer);
+  }
 }
@@ -1,18 +1,30 @@
-use sdl2::Sdl;
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::Read;
-use std::rc::Rc;
+use std::path::Path;
 
+use super::battery::*;
+use super::cartridge::*;
 use super::cpu::*;
-use super::debugger::*;
+use super::display_adapter::*;
 use super::graphics::*;
 use super::input::*;
+use super::interrupt::*;
 use super::mem::*;
 use super::serial::*;
 use super::sound::*;
 use super::timer::*;
 use super::util::*;
 
+pub const CPU_FREQUENCY: u64 = 4_194_304;
+pub const CYCLES_PER_FRAME: u64 = 70_224;
+// ~59.73 Hz.
+pub const FRAME_RATE: f64 = CPU_FREQUENCY as f64 / CYCLES_PER_FRAME as f64;
+const MAX_CARTRIDGE_EVENTS: usize = 256;
+
 #[rustfmt::skip]
 const OPCODE_DUR: [u8; 256] = [
    4, 12,  8,  8,  4,  4,  8,  4, 20,  8,  8,  8,  4,  4,  8,  4,
@@ -27,10 +39,10 @@ const OPCODE_DUR: [u8; 256] = [
    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
-  20, 12, 16, 16, 24, 16,  8, 16, 20, 16, 16,  4, 24, 24,  8, 16,
-  20, 12, 16,  0, 24, 16,  8, 16, 20, 16, 16,  0, 24,  0,  8, 16,
-  12, 12,  8,  0,  0, 16,  8, 16, 16,  4, 16,  0,  0,  0,  8, 16,
-  12, 12,  8,  4,  0, 16,  8, 16, 12,  8, 16,  4,  0,  0,  8, 16,
+  20, 12, 16, 16, 24, 16,  8, 16, 20, 16, 16,  0, 24, 24,  8, 16,
+  20, 12, 16,  4, 24, 16,  8, 16, 20, 16, 16,  4, 24,  4,  8, 16,
+  12, 12,  8,  4,  4, 16,  8, 16, 16,  4, 16,  4,  4,  4,  8, 16,
+  12, 12,  8,  4,  4, 16,  8, 16, 12,  8, 16,  4,  4,  4,  8, 16,
 ];
 
 #[rustfmt::skip]
@@ -59,10 +71,10 @@ const OPCODE_DUR_PREFIX: [u8; 256] = [
   8,  8,  8,  8,  8,  8, 16,  8,  8,  8,  8,  8,  8,  8, 16,  8,
   8,  8,  8,  8,  8,  8, 16,  8,  8,  8,  8,  8,  8,  8, 16,  8,
   8,  8,  8,  8,  8,  8, 16,  8,  8,  8,  8,  8,  8,  8, 16,  8,
-  8,  8,  8,  8,  8,  8, 16,  8,  8,  8,  8,  8,  8,  8, 16,  8,
-  8,  8,  8,  8,  8,  8, 16,  8,  8,  8,  8,  8,  8,  8, 16,  8,
-  8,  8,  8,  8,  8,  8, 16,  8,  8,  8,  8,  8,  8,  8, 16,  8,
-  8,  8,  8,  8,  8,  8, 16,  8,  8,  8,  8,  8,  8,  8, 16,  8,
+  8,  8,  8,  8,  8,  8, 12,  8,  8,  8,  8,  8,  8,  8, 12,  8,
+  8,  8,  8,  8,  8,  8, 12,  8,  8,  8,  8,  8,  8,  8, 12,  8,
+  8,  8,  8,  8,  8,  8, 12,  8,  8,  8,  8,  8,  8,  8, 12,  8,
+  8,  8,  8,  8,  8,  8, 12,  8,  8,  8,  8,  8,  8,  8, 12,  8,
   8,  8,  8,  8,  8,  8, 16,  8,  8,  8,  8,  8,  8,  8, 16,  8,
   8,  8,  8,  8,  8,  8, 16,  8,  8,  8,  8,  8,  8,  8, 16,  8,
   8,  8,  8,  8,  8,  8, 16,  8,  8,  8,  8,  8,  8,  8, 16,  8,
@@ -73,6 +85,73 @@ const OPCODE_DUR_PREFIX: [u8; 256] = [
   8,  8,  8,  8,  8,  8, 16,  8,  8,  8,  8,  8,  8,  8, 16,  8,
 ];
 
+#[derive(Debug)]
+pub enum EmuError {
+  Io(io::Error),
+  Cartridge(CartridgeError),
+  // Write to an address or IO port that is not emulated. The PC points to the instruction doing the write.
+  UnmappedWrite {
+    addr: u16,
+    value: u8,
+    pc: u16,
+    cpu: Cpu,
+  },
+  // Read of an address the bus routed to a device that does not map it.
+  UnmappedRead {
+    addr: u16,
+    pc: u16,
+    cpu: Cpu,
+  },
+  // Instruction fetch from the unusable area or the IO registers.
+  InvalidPc {
+    pc: u16,
+    cpu: Cpu,
+  },
+}
+
+impl fmt::Display for EmuError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      EmuError::Io(err) => write!(f, "Cannot read file: {}", err),
+      EmuError::Cartridge(err) => write!(f, "Cannot load cartridge: {}", err),
+      EmuError::UnmappedWrite {
+        addr,
+        value,
+        pc,
+        cpu,
+      } => write!(
+        f,
+        "Write of 0x{:>02x} to unmapped address 0x{:>04x} at PC: 0x{:>04x}\n{:#x?}",
+        value, addr, pc, cpu
+      ),
+      EmuError::UnmappedRead { addr, pc, cpu } => write!(
+        f,
+        "Read of unmapped address 0x{:>04x} at PC: 0x{:>04x}\n{:#x?}",
+        addr, pc, cpu
+      ),
+      EmuError::InvalidPc { pc, cpu } => write!(
+        f,
+        "PC is in non-executable memory: 0x{:>04x}\n{:#x?}",
+        pc, cpu
+      ),
+    }
+  }
+}
+
+impl Error for EmuError {}
+
+impl From<io::Error> for EmuError {
+  fn from(err: io::Error) -> EmuError {
+    EmuError::Io(err)
+  }
+}
+
+impl From<CartridgeError> for EmuError {
+  fn from(err: CartridgeError) -> EmuError {
+    EmuError::Cartridge(err)
+  }
+}
+
 pub struct Emu {
   pub cpu: Cpu,
   pub mem: Mem,
@@ -81,186 +160,233 @@ pub struct Emu {
   pub timer: Timer,
   pub serial: Serial,
   pub input: Input,
+  pub interrupts: InterruptController,
   dmg_rom: Vec<u8>,
   pub cycles: u64, // = m-cycle (= 1/4 tstate / 1/4 clock)
-  debugger: Option<Debugger>,
-  halted: bool,
-  rom: Vec<u8>,
-  rom_bank_number: u8,
-  interrupts_enabled: bool,
-  interrupts_enabled_new_value: bool,
-  pre_interrupt_status: u8,
+  pub cartridge: Cartridge,
+  battery_save: Option<BatterySave>,
   internal_rom_disabled: bool,
-  sdl: Rc<Sdl>,
-  iteration_count: u64,
+  frame_count: u64,
   is_stopped: bool,
+  is_cpu_locked: bool,
+  is_halted: bool,
+  has_halt_bug: bool,
+  // Address of the instruction being executed.
+  instruction_pc: u16,
+  // Kept until the frontend drains them.
+  cartridge_events: Vec<CartridgeEvent>,
+  // Errors can't be returned from the middle of an instruction, the first one is kept until the step finishes.
+  error: Cell<Option<EmuError>>,
 }
 
 impl Emu {
-  pub fn new(rom_file: String) -> Emu {
-    let sdl = Rc::new(sdl2::init().unwrap());
-
+  // Loads the cartridge with its battery save file (if it has a battery).
+  pub fn new(rom_file: String) -> Result<Emu, EmuError> {
     let mut rom = Vec::new();
-    let mut rom_file = File::open(rom_file).unwrap();
-    let _ = rom_file.read_to_end(&mut rom).unwrap();
+    File::open(&rom_file)?.read_to_end(&mut rom)?;
+
+    Emu::with_cartridge(rom, Some(BatterySave::new(Path::new(&rom_file))))
+  }
+
+  // From a ROM image in memory, without battery save files.
+  pub fn from_bytes(rom: Vec<u8>) -> Result<Emu, EmuError> {
+    Emu::with_cartridge(rom, None)
+  }
+
+  fn with_cartridge(rom: Vec<u8>, battery_save: Option<BatterySave>) -> Result<Emu, EmuError> {
+    let mut cartridge = Cartridge::from_bytes(rom)?;
+
+    let battery_save = battery_save.filter(|_| cartridge.has_battery());
+    if let Some(battery_save) = battery_save.as_ref() {
+      battery_save.load(&mut cartridge);
+    }
 
     let mut emu: Emu = Emu {
       cpu: Cpu::default(),
       mem: Mem::default(),
-      sound: Sound::new(sdl.clone()),
-      graphics: Graphics::new(sdl.clone()),
+      sound: Sound::new(),
+      graphics: Graphics::new(),
       timer: Timer::default(),
       serial: Serial::default(),
       input: Input::default(),
+      interrupts: InterruptController::default(),
       dmg_rom: Vec::new(),
       cycles: 0u64,
-      debugger: None,
-      halted: false,
-      rom,
-      rom_bank_number: 1,
-      interrupts_enabled: false,
-      interrupts_enabled_new_value: false,
-      pre_interrupt_status: 0,
+      cartridge,
+      battery_save,
       internal_rom_disabled: false,
-      sdl: sdl.clone(),
-      iteration_count: 0u64,
+      frame_count: 0u64,
       is_stopped: false,
+      is_cpu_locked: false,
+      is_halted: false,
+      has_halt_bug: false,
+      instruction_pc: 0,
+      cartridge_events: vec![],
+      error: Cell::new(None),
     };
 
     emu.reset();
-    emu.read_dmg_rom();
-    emu
-  }
-
-  pub fn enable_debug_mode(&mut self) {
-    self.debugger = Some(Debugger::new(self.sdl.clone()));
+    Ok(emu)
   }
 
-  pub fn run(&mut self) {
-    let mut cycles_prev = 0u64;
-    loop {
-      if let Some(debugger) = self.debugger.as_mut() {
-        let should_break = debugger.should_break(&self.cpu);
-        if should_break {
-          self.operate_debugger();
-        }
-      }
-
-      if self.halted {
-        return;
-      }
+  // Executes one instruction (or interrupt dispatch, or idle m-cycle in HALT) and advances the rest of the
+  // hardware with it. Returns the elapsed t-cycles, 0 in STOP mode.
+  pub fn step_instruction(&mut self) -> Result<u64, EmuError> {
+    if self.is_stopped {
+      return Ok(0);
+    }
 
-      self.interrupts_enabled_new_value = self.interrupts_enabled;
+    let cycles_prev = self.cycles;
+    self.instruction_pc = self.cpu.pc;
 
-      if !self.is_stopped {
+    // Interrupt dispatch takes the place of the next instruction fetch.
+    if !self.handle_interrupts() {
+      if self.is_halted || self.is_cpu_locked {
+        // The CPU does not fetch instructions, but the rest of the hardware keeps running.
+        self.cycles += 4;
+      } else if is_pc_executable(self.cpu.pc) {
         self.read_instruction();
+      } else {
+        return Err(EmuError::InvalidPc {
+          pc: self.cpu.pc,
+          cpu: self.cpu,
+        });
       }
+    }
 
-      self.handle_timer(cycles_prev);
+    self.handle_timer(cycles_prev);
+    self.handle_serial(cycles_prev);
+    self.handle_graphics(cycles_prev);
+    self.sound.update(cycles_prev, self.cycles);
+    self.handle_cartridge_events();
+    self.handle_battery_save();
 
-      if !self.is_stopped {
-        self.handle_graphics(cycles_prev);
-      }
-      self.handle_input_check();
+    match self.error.take() {
+      Some(error) => Err(error),
+      None => Ok(self.cycles - cycles_prev),
+    }
+  }
 
-      self.handle_interrupts();
+  // Runs until the next frame is completed or the system gets stopped.
+  pub fn run_frame(&mut self) -> Result<(), EmuError> {
+    self.run_frames(1)
+  }
 
-      cycles_prev = self.cycles;
-      self.interrupts_enabled = self.interrupts_enabled_new_value;
+  pub fn run_frames(&mut self, frames: u64) -> Result<(), EmuError> {
+    let target = self.frame_count + frames;
+    while self.frame_count < target && !self.is_stopped {
+      self.step_instruction()?;
+    }
+    Ok(())
+  }
 
-      if self.iteration_count & 0xfff == 0 {
-        if let Some(dbgr) = self.debugger.as_mut() {
-          dbgr.update_debug_windows(self.iteration_count, &self.cpu, &self.graphics);
-        }
-      }
+  // Runs at least the given number of t-cycles (unless the system gets stopped).
+  pub fn run_cycles(&mut self, cycles: u64) -> Result<(), EmuError> {
+    let target = self.cycles + cycles;
+    while self.cycles < target && !self.is_stopped {
+      self.step_instruction()?;
+    }
+    Ok(())
+  }
 
-      self.iteration_count += 1;
+  // In STOP mode the system clock is halted, only a joypad press can wake it up.
+  pub fn is_stopped(&self) -> bool {
+    self.is_stopped
+  }
+
+  pub fn set_buttons(&mut self, buttons: Buttons) {
+    if self.input.set_buttons(buttons) {
+      // Any button press wakes up the system from STOP mode.
+      self.is_stopped = false;
     }
+    self.handle_input();
   }
 
-  fn operate_debugger(&mut self) {
-    let command = self.debugger.as_mut().unwrap().read_command();
-    match command {
-      DebuggerCommand::Quit => {
-        self.halted = true;
-        return;
-      }
-      DebuggerCommand::MemoryPrint(addr, len) => {
-        self.mem_debug_print(addr, len);
-      }
-      DebuggerCommand::CpuPrint => self.cpu.registers_debug_print(),
-      DebuggerCommand::Breakpoint => { /* keep it stopped */ }
-      DebuggerCommand::Continue | DebuggerCommand::Next => {
-        self.debugger.as_mut().unwrap().update_debug_windows(
-          self.iteration_count,
-          &self.cpu,
-          &self.graphics,
-        );
-        return;
-      }
-      DebuggerCommand::Display => self.graphics.draw_display(),
-      DebuggerCommand::PrintBackgroundMap => {
-        self.debugger.as_mut().unwrap().update_debug_windows(
-          self.iteration_count,
-          &self.cpu,
-          &self.graphics,
-        );
-      }
-      DebuggerCommand::History => self.debugger.as_ref().unwrap().print_history(),
-      _ => {}
-    };
+  pub fn frame_count(&self) -> u64 {
+    self.frame_count
+  }
 
-    self.operate_debugger();
+  // The last completed frame.
+  pub fn framebuffer(&self) -> &Framebuffer {
+    self.graphics.framebuffer()
   }
 
-  fn handle_interrupts(&mut self) {
-    if !self.interrupts_enabled {
-      return;
-    }
+  // Audio samples generated since the last call, interleaved stereo (left, right). Up to a second is kept, the older
+  // samples are dropped.
+  pub fn drain_audio(&mut self) -> Vec<f32> {
+    self.sound.drain_samples()
+  }
 
-    info!("Interrupt check.");
-    // dbg!("INTERRUPT CHECK");
-
-    if self.interrupt_enabled_v_blank() && self.interrupt_flag_v_blank() {
-      self.exec_interrupt(0, 0x40);
-    } else if self.interrupt_enabled_lcd_stat() && self.interrupt_flag_lcd_stat() {
-      self.exec_interrupt(0, 0x48);
-    } else if self.interrupt_enabled_timer() && self.interrupt_flag_timer() {
-      self.exec_interrupt(0, 0x50);
-    } else if self.interrupt_enabled_serial() && self.interrupt_flag_serial() {
-      self.exec_interrupt(0, 0x58);
-    } else if self.interrupt_enabled_joypad() && self.interrupt_flag_joypad() {
-      self.exec_interrupt(0, 0x60);
-    }
+  // Sample rate of the audio, 44.1 kHz by default.
+  pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+    self.sound.set_sample_rate(sample_rate);
   }
 
-  fn exec_interrupt(&mut self, interrupt_bit_num: u32, interrupt_addr: u16) {
-    self.pre_interrupt_status = self.read_word(0xff0f, false);
+  // Generates `rate_adjustment` (close to 1.0) times the samples of the sample rate, to keep an audio buffer level.
+  pub fn set_audio_rate_adjustment(&mut self, rate_adjustment: f64) {
+    self.sound.set_rate_adjustment(rate_adjustment);
+  }
 
-    // Disable interrupts.
-    self.interrupts_enabled = false;
+  // Bytes sent through the serial port.
+  pub fn serial_output(&self) -> &[u8] {
+    self.serial.output()
+  }
 
-    // Disable specific interrupt request.
-    let flag_off = Util::setbit(self.read_word(0xff0f, false), interrupt_bit_num, 0x0);
-    self.write_word(0xff0f, flag_off);
-    self.cycles += 2;
+  pub fn drain_serial_output(&mut self) -> Vec<u8> {
+    self.serial.drain_output()
+  }
 
-    // Save current PC.
-    self.push_dword(self.cpu.pc);
-    self.cycles += 2;
+  // Cartridge hardware events (eg. the rumble motor turned on or off) since the last call.
+  pub fn drain_cartridge_events(&mut self) -> Vec<CartridgeEvent> {
+    self.cartridge_events.drain(..).collect()
+  }
 
-    // Jump to interrupt instruction.
-    self.cpu.pc = interrupt_addr;
-    self.cycles += 1;
-    // TODO These cycle advances needs to be considered for tick detection!!! -> Maybe not needed.
+  // Reads the memory as the CPU sees it, except VRAM and OAM are accessible in any PPU mode.
+  pub fn read_memory(&self, addr: u16) -> u8 {
+    self.read_word(addr, true)
+  }
+
+  // Writes the memory as the CPU would, so MBC and IO registers react to it.
+  pub fn write_memory(&mut self, addr: u16, w: u8) {
+    self.write_word(addr, w);
+  }
+
+  // Returns true when an interrupt was dispatched.
+  fn handle_interrupts(&mut self) -> bool {
+    if self.is_cpu_locked {
+      return false;
+    }
+
+    if self.is_halted && self.interrupts.has_pending() {
+      // HALT is left on any pending interrupt, even if the interrupt master enable flag is off.
+      self.is_halted = false;
+    }
+
+    let serviced = self.interrupts.service();
+    self.interrupts.apply_delayed_enable();
+
+    match serviced {
+      Some(interrupt) => {
+        info!("Interrupt dispatch: {:?}", interrupt);
+        self.exec_interrupt(interrupt);
+        true
+      }
+      None => false,
+    }
+  }
+
+  fn exec_interrupt(&mut self, interrupt: Interrupt) {
+    // Dispatch takes 5 m-cycles: 2 idle, 2 to push PC and 1 to jump to the vector.
+    self.push_dword(self.cpu.pc);
+    self.cpu.pc = interrupt.vector();
+    self.cycles += 20;
   }
 
   fn handle_timer(&mut self, cycles_prev: u64) {
     let timer_result = self.timer.update(cycles_prev, self.cycles);
 
     if timer_result.interrupt_generated {
-      let new_interrupts = Util::setbit(self.read_word(0xff0f, false), 2, 0x1);
-      self.write_word(0xff0f, new_interrupts);
+      self.interrupts.request(Interrupt::Timer);
     }
   }
 
@@ -268,34 +394,66 @@ impl Emu {
     let response = self.graphics.update(cycles_prev, self.cycles);
 
     if response.vblank_interrupt_generated {
-      let new_interrupts = Util::setbit(self.read_word(0xff0f, false), 0, 0x1);
-      self.write_word(0xff0f, new_interrupts);
+      self.interrupts.request(Interrupt::VBlank);
     }
 
     if response.lcd_stat_interrupt_generated {
-      let new_interrupts = Util::setbit(self.read_word(0xff0f, false), 1, 0x1);
-      self.write_word(0xff0f, new_interrupts);
+      self.interrupts.request(Interrupt::LcdStat);
+    }
+
+    if response.frame_ready {
+      self.frame_count += 1;
     }
   }
 
-  fn handle_input_check(&mut self) {
-    // So far the event loop poll is the most expensive operation.
-    // It seems it's not important to poll events on every single instruction.
-    if self.iteration_count % 100 != 0 {
-      return;
+  fn handle_serial(&mut self, cycles_prev: u64) {
+    if self
+      .serial
+      .update(cycles_prev, self.cycles)
+      .interrupt_generated
+    {
+      self.interrupts.request(Interrupt::Serial);
     }
+  }
 
-    for event in self.sdl.event_pump().unwrap().poll_iter() {
-      match event {
-        sdl2::event::Event::Quit { .. } => self.halted = true,
-        // @TODO Implement key listening.
-        _ => {}
-      }
+  fn handle_input(&mut self) {
+    if self.input.take_interrupt() {
+      self.interrupts.request(Interrupt::Joypad);
+    }
+  }
+
+  // The oldest events are dropped when the frontend doesn't drain them.
+  fn handle_cartridge_events(&mut self) {
+    let events = self.cartridge.drain_events();
+    self.cartridge_events.extend(events);
+
+    if self.cartridge_events.len() > MAX_CARTRIDGE_EVENTS {
+      let excess = self.cartridge_events.len() - MAX_CARTRIDGE_EVENTS;
+      self.cartridge_events.drain(..excess);
+    }
+  }
+
+  fn handle_battery_save(&mut self) {
+    if let Some(battery_save) = self.battery_save.as_mut() {
+      battery_save.update(&mut self.cartridge, self.cycles);
+    }
+  }
+
+  // Writes the cartridge RAM to the save file if it changed since the last write. The frontend calls it on exit.
+  pub fn flush_battery_save(&mut self) {
+    if let Some(battery_save) = self.battery_save.as_mut() {
+      battery_save.flush(&self.cartridge, self.cycles);
     }
   }
 
   pub fn read_instruction(&mut self) {
-    let opcode = self.read_opcode_word();
+    let opcode = if self.has_halt_bug {
+      // HALT bug: PC fails to increment after HALT, so the next byte is read twice.
+      self.has_halt_bug = false;
+      self.read_word(self.cpu.pc, false)
+    } else {
+      self.read_opcode_word()
+    };
     let mut is_cycle_alternative = false;
 
     info!(
@@ -356,7 +514,12 @@ impl Emu {
         self.cpu.set_flag_carry(bit0);
       }
       // 0x10 | STOP 0 | 2 | 4 | - - - -
-      0x10 => self.is_stopped = true,
+      0x10 => {
+        // STOP is followed by a (normally 0x00) padding byte.
+        self.read_opcode_word();
+        self.timer.reset_divider();
+        self.is_stopped = true;
+      }
       // 0x11 | LD DE,d16 | 3 | 12 | - - - -
       0x11 => load_dword_to_reg!(set_de, self),
       // 0x12 | LD (DE),A | 1 | 8 | - - - -
@@ -530,11 +693,16 @@ impl Emu {
         w = w.wrapping_sub(0b1);
         self.cpu.set_flag_zero_for(w);
         self.cpu.set_flag_add_sub(0b1);
+        self.write_word(self.cpu.reg_hl(), w);
       }
       // 0x36 | LD (HL),d8 | 2 | 12 | - - - -
       0x36 => load_word_to_reg_addr!(reg_h, reg_l, self),
       // 0x37 | SCF | 1 | 4 | - 0 0 1
-      0x37 => unimplemented!("Opcode 0x37 is not yet implemented"),
+      0x37 => {
+        self.cpu.reset_flag_add_sub();
+        self.cpu.reset_flag_half_carry();
+        self.cpu.set_flag_carry(0x1);
+      }
       // 0x38 | JR C,r8 | 2 | 12/8 | - - - -
       0x38 => {
         let offs = self.read_opcode_word();
@@ -552,7 +720,7 @@ impl Emu {
         self.cpu.dec_hl();
       }
       // 0x3b | DEC SP | 1 | 8 | - - - -
-      0x3b => unimplemented!("Opcode 0x3b is not yet implemented"),
+      0x3b => self.cpu.sp = self.cpu.sp.wrapping_sub(1),
       // 0x3c | INC A | 1 | 4 | Z 0 H -
       0x3c => op_inc_reg!(self, reg_a),
       // 0x3d | DEC A | 1 | 4 | Z 1 H -
@@ -560,7 +728,12 @@ impl Emu {
       // 0x3e | LD A,d8 | 2 | 8 | - - - -
       0x3e => load_word_to_reg!(reg_a, self),
       // 0x3f | CCF | 1 | 4 | - 0 0 C
-      0x3f => unimplemented!("Opcode 0x3f is not yet implemented"),
+      0x3f => {
+        let carry = self.cpu.flag_carry();
+        self.cpu.reset_flag_add_sub();
+        self.cpu.reset_flag_half_carry();
+        self.cpu.set_flag_carry((!carry).as_bit());
+      }
       // 0x40 | LD B,B | 1 | 4 | - - - -
       0x40 => load_word_to_reg_from_reg!(reg_b, reg_b, self),
       // 0x41 | LD B,C | 1 | 4 | - - - -
@@ -670,7 +843,13 @@ impl Emu {
       // 0x75 | LD (HL),L | 1 | 8 | - - - -
       0x75 => load_word_to_reg_addr_from_reg!(reg_h, reg_l, reg_l, self),
       // 0x76 | HALT | 1 | 4 | - - - -
-      0x76 => unimplemented!("Opcode 0x76 is not yet implemented"),
+      0x76 => {
+        if !self.interrupts.is_master_enabled() && self.interrupts.has_pending() {
+          self.has_halt_bug = true;
+        } else {
+          self.is_halted = true;
+        }
+      }
       // 0x77 | LD (HL),A | 1 | 8 | - - - -
       0x77 => load_word_to_reg_addr_from_reg!(reg_h, reg_l, reg_a, self),
       // 0x78 | LD A,B | 1 | 4 | - - - -
@@ -738,21 +917,21 @@ impl Emu {
       // 0x97 | SUB A | 1 | 4 | Z 1 H C
       0x97 => op_sub_reg_from_a!(self, self.cpu.reg_a),
       // 0x98 | SBC A,B | 1 | 4 | Z 1 H C
-      0x98 => unimplemented!("Opcode 0x98 is not yet implemented"),
+      0x98 => sbc_a!(self, self.cpu.reg_b),
       // 0x99 | SBC A,C | 1 | 4 | Z 1 H C
-      0x99 => unimplemented!("Opcode 0x99 is not yet implemented"),
+      0x99 => sbc_a!(self, self.cpu.reg_c),
       // 0x9a | SBC A,D | 1 | 4 | Z 1 H C
-      0x9a => unimplemented!("Opcode 0x9a is not yet implemented"),
+      0x9a => sbc_a!(self, self.cpu.reg_d),
       // 0x9b | SBC A,E | 1 | 4 | Z 1 H C
-      0x9b => unimplemented!("Opcode 0x9b is not yet implemented"),
+      0x9b => sbc_a!(self, self.cpu.reg_e),
       // 0x9c | SBC A,H | 1 | 4 | Z 1 H C
-      0x9c => unimplemented!("Opcode 0x9c is not yet implemented"),
+      0x9c => sbc_a!(self, self.cpu.reg_h),
       // 0x9d | SBC A,L | 1 | 4 | Z 1 H C
-      0x9d => unimplemented!("Opcode 0x9d is not yet implemented"),
+      0x9d => sbc_a!(self, self.cpu.reg_l),
       // 0x9e | SBC A,(HL) | 1 | 8 | Z 1 H C
-      0x9e => unimplemented!("Opcode 0x9e is not yet implemented"),
+      0x9e => sbc_a!(self, self.read_word(self.cpu.reg_hl(), false)),
       // 0x9f | SBC A,A | 1 | 4 | Z 1 H C
-      0x9f => unimplemented!("Opcode 0x9f is not yet implemented"),
+      0x9f => sbc_a!(self, self.cpu.reg_a),
       // 0xa0 | AND B | 1 | 4 | Z 0 1 0
       0xa0 => and_reg!(self, self.cpu.reg_b),
       // 0xa1 | AND C | 1 | 4 | Z 0 1 0
@@ -885,6 +1064,7 @@ impl Emu {
         }
       }
       // 0xcb | PREFIX CB | 1 | 4 | - - - -
+      // The prefix fetch is already included in the durations of OPCODE_DUR_PREFIX.
       0xcb => self.read_prefix_instruction(),
       // 0xcc | CALL Z,a16 | 3 | 24/12 | - - - -
       0xcc => {
@@ -972,11 +1152,9 @@ impl Emu {
       }
       // 0xd9 | RETI | 1 | 16 | - - - -
       0xd9 => {
-        // panic!("{:#x?} {:#x?} {:#x?}", self.cpu, self.pre_interrupt_status, self.read_word(0xff0f, false));
-        // @TODO - Look into this why we get infinite loop. (BGB does not do anything.)
-        // self.write_word(0xff0f, self.pre_interrupt_status);
         let addr = self.pop_dword();
         self.cpu.pc = addr;
+        self.interrupts.enable();
       }
       // 0xda | JP C,a16 | 3 | 16/12 | - - - -
       0xda => {
@@ -998,7 +1176,10 @@ impl Emu {
         }
       }
       // 0xde | SBC A,d8 | 2 | 8 | Z 1 H C
-      0xde => unimplemented!("Opcode 0xde is not yet implemented"),
+      0xde => {
+        let w = self.read_opcode_word();
+        sbc_a!(self, w);
+      }
       // 0xdf | RST 18H | 1 | 16 | - - - -
       0xdf => rst!(0x18, self),
       // 0xe0 | LDH (a8),A | 2 | 12 | - - - -
@@ -1029,7 +1210,10 @@ impl Emu {
       // 0xe7 | RST 20H | 1 | 16 | - - - -
       0xe7 => rst!(0x20, self),
       // 0xe8 | ADD SP,r8 | 2 | 16 | 0 0 H C
-      0xe8 => unimplemented!("Opcode 0xe8 is not yet implemented"),
+      0xe8 => {
+        let w = self.read_opcode_word();
+        self.cpu.sp = sp_add_signed_word!(self, w);
+      }
       // 0xe9 | JP (HL) | 1 | 4 | - - - -
       0xe9 => self.cpu.pc = self.cpu.reg_hl(),
       // 0xea | LD (a16),A | 3 | 16 | - - - -
@@ -1059,9 +1243,12 @@ impl Emu {
         self.cpu.set_af(dw);
       }
       // 0xf2 | LD A,(C) | 2 | 8 | - - - -
-      0xf2 => unimplemented!("Opcode 0xf2 is not yet implemented"),
+      0xf2 => {
+        let addr = 0xff00 | self.cpu.reg_c as u16;
+        self.cpu.reg_a = self.read_word(addr, false);
+      }
       // 0xf3 | DI | 1 | 4 | - - - -
-      0xf3 => self.interrupts_enabled_new_value = false,
+      0xf3 => self.interrupts.disable(),
       // 0xf5 | PUSH AF | 1 | 16 | - - - -
       0xf5 => self.push_dword(self.cpu.reg_af()),
       // 0xf6 | OR d8 | 2 | 8 | Z 0 0 0
@@ -1071,19 +1258,7 @@ impl Emu {
       // 0xf8 | LD HL,SP+r8 | 2 | 12 | 0 0 H C
       0xf8 => {
         let w = self.read_opcode_word();
-        let offs = w as i8;
-        let dw = Util::dword_signed_add(self.cpu.sp, offs);
-
-        self.cpu.reset_flag_zero();
-        self.cpu.reset_flag_add_sub();
-
-        self
-          .cpu
-          .set_flag_half_carry(Util::has_dw_half_carry(self.cpu.sp, w as u16).as_bit());
-        self
-          .cpu
-          .set_flag_carry(Util::has_dw_carry(self.cpu.sp, w as u16).as_bit());
-
+        let dw = sp_add_signed_word!(self, w);
         self.cpu.set_hl(dw);
       }
       // 0xf9 | LD SP,HL | 1 | 8 | - - - -
@@ -1091,7 +1266,7 @@ impl Emu {
       // 0xfa | LD A,(a16) | 3 | 16 | - - - -
       0xfa => load_word_to_reg_from_dword_addr!(reg_a, self),
       // 0xfb | EI | 1 | 4 | - - - -
-      0xfb => self.interrupts_enabled_new_value = true,
+      0xfb => self.interrupts.enable_delayed(),
       // 0xfe | CP d8 | 2 | 8 | Z 1 H C
       0xfe => {
         let w = self.read_opcode_word();
@@ -1099,7 +1274,15 @@ impl Emu {
       }
       // 0xff | RST 38H | 1 | 16 | - - - -
       0xff => rst!(0x38, self),
-      opcode @ _ => panic!("Unexpected opcode: {:?}", opcode),
+      // Illegal opcodes - the real hardware locks up until it's powered down.
+      0xd3 | 0xdb | 0xdd | 0xe3 | 0xe4 | 0xeb | 0xec | 0xed | 0xf4 | 0xfc | 0xfd => {
+        warn!(
+          "Illegal opcode 0x{:>02x} at PC: 0x{:>04x}, CPU is locked",
+          opcode,
+          self.cpu.pc - 1
+        );
+        self.is_cpu_locked = true;
+      }
     };
 
     if is_cycle_alternative {
@@ -1120,37 +1303,65 @@ impl Emu {
 
     match opcode {
       // 0x00 | RLC B | 2 | 8 | Z 0 0 C
-      0x00 => unimplemented!("Prefix opcode 0x00 is not yet implemented"),
+      0x00 => rlc!(self, reg_b),
       // 0x01 | RLC C | 2 | 8 | Z 0 0 C
-      0x01 => unimplemented!("Prefix opcode 0x01 is not yet implemented"),
+      0x01 => rlc!(self, reg_c),
       // 0x02 | RLC D | 2 | 8 | Z 0 0 C
-      0x02 => unimplemented!("Prefix opcode 0x02 is not yet implemented"),
+      0x02 => rlc!(self, reg_d),
       // 0x03 | RLC E | 2 | 8 | Z 0 0 C
-      0x03 => unimplemented!("Prefix opcode 0x03 is not yet implemented"),
+      0x03 => rlc!(self, reg_e),
       // 0x04 | RLC H | 2 | 8 | Z 0 0 C
-      0x04 => unimplemented!("Prefix opcode 0x04 is not yet implemented"),
+      0x04 => rlc!(self, reg_h),
       // 0x05 | RLC L | 2 | 8 | Z 0 0 C
-      0x05 => unimplemented!("Prefix opcode 0x05 is not yet implemented"),
+      0x05 => rlc!(self, reg_l),
       // 0x06 | RLC (HL) | 2 | 16 | Z 0 0 C
-      0x06 => unimplemented!("Prefix opcode 0x06 is not yet implemented"),
+      0x06 => {
+        let addr = self.cpu.reg_hl();
+        let mut w = self.read_word(addr, false);
+
+        let bit7 = bitn!(w, 7);
+        self.cpu.set_flag_carry(bit7);
+
+        w = (w << 1) | bit7;
+
+        self.write_word(addr, w);
+        self.cpu.set_flag_zero_for(w);
+
+        self.cpu.reset_flag_add_sub();
+        self.cpu.reset_flag_half_carry();
+      }
       // 0x07 | RLC A | 2 | 8 | Z 0 0 C
-      0x07 => unimplemented!("Prefix opcode 0x07 is not yet implemented"),
+      0x07 => rlc!(self, reg_a),
       // 0x08 | RRC B | 2 | 8 | Z 0 0 C
-      0x08 => unimplemented!("Prefix opcode 0x08 is not yet implemented"),
+      0x08 => rrc!(self, reg_b),
       // 0x09 | RRC C | 2 | 8 | Z 0 0 C
-      0x09 => unimplemented!("Prefix opcode 0x09 is not yet implemented"),
+      0x09 => rrc!(self, reg_c),
       // 0x0a | RRC D | 2 | 8 | Z 0 0 C
-      0x0a => unimplemented!("Prefix opcode 0x0a is not yet implemented"),
+      0x0a => rrc!(self, reg_d),
       // 0x0b | RRC E | 2 | 8 | Z 0 0 C
-      0x0b => unimplemented!("Prefix opcode 0x0b is not yet implemented"),
+      0x0b => rrc!(self, reg_e),
       // 0x0c | RRC H | 2 | 8 | Z 0 0 C
-      0x0c => unimplemented!("Prefix opcode 0x0c is not yet implemented"),
+      0x0c => rrc!(self, reg_h),
       // 0x0d | RRC L | 2 | 8 | Z 0 0 C
-      0x0d => unimplemented!("Prefix opcode 0x0d is not yet implemented"),
+      0x0d => rrc!(self, reg_l),
       // 0x0e | RRC (HL) | 2 | 16 | Z 0 0 C
-      0x0e => unimplemented!("Prefix opcode 0x0e is not yet implemented"),
+      0x0e => {
+        let addr = self.cpu.reg_hl();
+        let mut w = self.read_word(addr, false);
+
+        let bit0 = bitn!(w, 0);
+        self.cpu.set_flag_carry(bit0);
+
+        w = (w >> 1) | (bit0 << 7);
+
+        self.write_word(addr, w);
+        self.cpu.set_flag_zero_for(w);
+
+        self.cpu.reset_flag_add_sub();
+        self.cpu.reset_flag_half_carry();
+      }
       // 0x0f | RRC A | 2 | 8 | Z 0 0 C
-      0x0f => unimplemented!("Prefix opcode 0x0f is not yet implemented"),
+      0x0f => rrc!(self, reg_a),
       // 0x10 | RL B | 2 | 8 | Z 0 0 C
       0x10 => rot_left_reg!(self, reg_b),
       // 0x11 | RL C | 2 | 8 | Z 0 0 C
@@ -1164,7 +1375,21 @@ impl Emu {
       // 0x15 | RL L | 2 | 8 | Z 0 0 C
       0x15 => rot_left_reg!(self, reg_l),
       // 0x16 | RL (HL) | 2 | 16 | Z 0 0 C
-      0x16 => unimplemented!("Prefix opcode 0x16 is not yet implemented"),
+      0x16 => {
+        let addr = self.cpu.reg_hl();
+        let mut w = self.read_word(addr, false);
+
+        let old_carry = self.cpu.flag_carry().as_bit();
+        self.cpu.set_flag_carry(bitn!(w, 7));
+
+        w = (w << 1) | old_carry;
+
+        self.write_word(addr, w);
+        self.cpu.set_flag_zero_for(w);
+
+        self.cpu.reset_flag_add_sub();
+        self.cpu.reset_flag_half_carry();
+      }
       // 0x17 | RL A | 2 | 8 | Z 0 0 C
       0x17 => rot_left_reg!(self, reg_a),
       // 0x18 | RR B | 2 | 8 | Z 0 0 C
@@ -1198,37 +1423,63 @@ impl Emu {
       // 0x1f | RR A | 2 | 8 | Z 0 0 C
       0x1f => rr!(self, reg_a),
       // 0x20 | SLA B | 2 | 8 | Z 0 0 C
-      0x20 => unimplemented!("Prefix opcode 0x20 is not yet implemented"),
+      0x20 => sla!(self, reg_b),
       // 0x21 | SLA C | 2 | 8 | Z 0 0 C
-      0x21 => unimplemented!("Prefix opcode 0x21 is not yet implemented"),
+      0x21 => sla!(self, reg_c),
       // 0x22 | SLA D | 2 | 8 | Z 0 0 C
-      0x22 => unimplemented!("Prefix opcode 0x22 is not yet implemented"),
+      0x22 => sla!(self, reg_d),
       // 0x23 | SLA E | 2 | 8 | Z 0 0 C
-      0x23 => unimplemented!("Prefix opcode 0x23 is not yet implemented"),
+      0x23 => sla!(self, reg_e),
       // 0x24 | SLA H | 2 | 8 | Z 0 0 C
-      0x24 => unimplemented!("Prefix opcode 0x24 is not yet implemented"),
+      0x24 => sla!(self, reg_h),
       // 0x25 | SLA L | 2 | 8 | Z 0 0 C
-      0x25 => unimplemented!("Prefix opcode 0x25 is not yet implemented"),
+      0x25 => sla!(self, reg_l),
       // 0x26 | SLA (HL) | 2 | 16 | Z 0 0 C
-      0x26 => unimplemented!("Prefix opcode 0x26 is not yet implemented"),
+      0x26 => {
+        let addr = self.cpu.reg_hl();
+        let mut w = self.read_word(addr, false);
+
+        self.cpu.set_flag_carry(bitn!(w, 7));
+
+        w = w << 1;
+
+        self.write_word(addr, w);
+        self.cpu.set_flag_zero_for(w);
+
+        self.cpu.reset_flag_add_sub();
+        self.cpu.reset_flag_half_carry();
+      }
       // 0x27 | SLA A | 2 | 8 | Z 0 0 C
-      0x27 => unimplemented!("Prefix opcode 0x27 is not yet implemented"),
-      // 0x28 | SRA B | 2 | 8 | Z 0 0 0
-      0x28 => unimplemented!("Prefix opcode 0x28 is not yet implemented"),
-      // 0x29 | SRA C | 2 | 8 | Z 0 0 0
-      0x29 => unimplemented!("Prefix opcode 0x29 is not yet implemented"),
-      // 0x2a | SRA D | 2 | 8 | Z 0 0 0
-      0x2a => unimplemented!("Prefix opcode 0x2a is not yet implemented"),
-      // 0x2b | SRA E | 2 | 8 | Z 0 0 0
-      0x2b => unimplemented!("Prefix opcode 0x2b is not yet implemented"),
-      // 0x2c | SRA H | 2 | 8 | Z 0 0 0
-      0x2c => unimplemented!("Prefix opcode 0x2c is not yet implemented"),
-      // 0x2d | SRA L | 2 | 8 | Z 0 0 0
-      0x2d => unimplemented!("Prefix opcode 0x2d is not yet implemented"),
-      // 0x2e | SRA (HL) | 2 | 16 | Z 0 0 0
-      0x2e => unimplemented!("Prefix opcode 0x2e is not yet implemented"),
-      // 0x2f | SRA A | 2 | 8 | Z 0 0 0
-      0x2f => unimplemented!("Prefix opcode 0x2f is not yet implemented"),
+      0x27 => sla!(self, reg_a),
+      // 0x28 | SRA B | 2 | 8 | Z 0 0 C
+      0x28 => sra!(self, reg_b),
+      // 0x29 | SRA C | 2 | 8 | Z 0 0 C
+      0x29 => sra!(self, reg_c),
+      // 0x2a | SRA D | 2 | 8 | Z 0 0 C
+      0x2a => sra!(self, reg_d),
+      // 0x2b | SRA E | 2 | 8 | Z 0 0 C
+      0x2b => sra!(self, reg_e),
+      // 0x2c | SRA H | 2 | 8 | Z 0 0 C
+      0x2c => sra!(self, reg_h),
+      // 0x2d | SRA L | 2 | 8 | Z 0 0 C
+      0x2d => sra!(self, reg_l),
+      // 0x2e | SRA (HL) | 2 | 16 | Z 0 0 C
+      0x2e => {
+        let addr = self.cpu.reg_hl();
+        let mut w = self.read_word(addr, false);
+
+        self.cpu.set_flag_carry(bitn!(w, 0));
+
+        w = (w >> 1) | (w & 0x80);
+
+        self.write_word(addr, w);
+        self.cpu.set_flag_zero_for(w);
+
+        self.cpu.reset_flag_add_sub();
+        self.cpu.reset_flag_half_carry();
+      }
+      // 0x2f | SRA A | 2 | 8 | Z 0 0 C
+      0x2f => sra!(self, reg_a),
       // 0x30 | SWAP B | 2 | 8 | Z 0 0 0
       0x30 => swap!(reg_b, self),
       // 0x31 | SWAP C | 2 | 8 | Z 0 0 0
@@ -1293,8 +1544,8 @@ impl Emu {
       0x44 => op_bit_test!(self, reg_h, 0),
       // 0x45 | BIT 0,L | 2 | 8 | Z 0 1 -
       0x45 => op_bit_test!(self, reg_l, 0),
-      // 0x46 | BIT 0,(HL) | 2 | 16 | Z 0 1 -
-      0x46 => unimplemented!("Prefix opcode 0x46 is not yet implemented"),
+      // 0x46 | BIT 0,(HL) | 2 | 12 | Z 0 1 -
+      0x46 => op_bit_test_hl!(self, 0),
       // 0x47 | BIT 0,A | 2 | 8 | Z 0 1 -
       0x47 => op_bit_test!(self, reg_a, 0),
       // 0x48 | BIT 1,B | 2 | 8 | Z 0 1 -
@@ -1309,8 +1560,8 @@ impl Emu {
       0x4c => op_bit_test!(self, reg_h, 1),
       // 0x4d | BIT 1,L | 2 | 8 | Z 0 1 -
       0x4d => op_bit_test!(self, reg_l, 1),
-      // 0x4e | BIT 1,(HL) | 2 | 16 | Z 0 1 -
-      0x4e => unimplemented!("Prefix opcode 0x4e is not yet implemented"),
+      // 0x4e | BIT 1,(HL) | 2 | 12 | Z 0 1 -
+      0x4e => op_bit_test_hl!(self, 1),
       // 0x4f | BIT 1,A | 2 | 8 | Z 0 1 -
       0x4f => op_bit_test!(self, reg_a, 1),
       // 0x50 | BIT 2,B | 2 | 8 | Z 0 1 -
@@ -1325,8 +1576,8 @@ impl Emu {
       0x54 => op_bit_test!(self, reg_h, 2),
       // 0x55 | BIT 2,L | 2 | 8 | Z 0 1 -
       0x55 => op_bit_test!(self, reg_l, 2),
-      // 0x56 | BIT 2,(HL) | 2 | 16 | Z 0 1 -
-      0x56 => unimplemented!("Prefix opcode 0x56 is not yet implemented"),
+      // 0x56 | BIT 2,(HL) | 2 | 12 | Z 0 1 -
+      0x56 => op_bit_test_hl!(self, 2),
       // 0x57 | BIT 2,A | 2 | 8 | Z 0 1 -
       0x57 => op_bit_test!(self, reg_a, 2),
       // 0x58 | BIT 3,B | 2 | 8 | Z 0 1 -
@@ -1341,8 +1592,8 @@ impl Emu {
       0x5c => op_bit_test!(self, reg_h, 3),
       // 0x5d | BIT 3,L | 2 | 8 | Z 0 1 -
       0x5d => op_bit_test!(self, reg_l, 3),
-      // 0x5e | BIT 3,(HL) | 2 | 16 | Z 0 1 -
-      0x5e => unimplemented!("Prefix opcode 0x5e is not yet implemented"),
+      // 0x5e | BIT 3,(HL) | 2 | 12 | Z 0 1 -
+      0x5e => op_bit_test_hl!(self, 3),
       // 0x5f | BIT 3,A | 2 | 8 | Z 0 1 -
       0x5f => op_bit_test!(self, reg_a, 3),
       // 0x60 | BIT 4,B | 2 | 8 | Z 0 1 -
@@ -1357,8 +1608,8 @@ impl Emu {
       0x64 => op_bit_test!(self, reg_h, 4),
       // 0x65 | BIT 4,L | 2 | 8 | Z 0 1 -
       0x65 => op_bit_test!(self, reg_l, 4),
-      // 0x66 | BIT 4,(HL) | 2 | 16 | Z 0 1 -
-      0x66 => unimplemented!("Prefix opcode 0x66 is not yet implemented"),
+      // 0x66 | BIT 4,(HL) | 2 | 12 | Z 0 1 -
+      0x66 => op_bit_test_hl!(self, 4),
       // 0x67 | BIT 4,A | 2 | 8 | Z 0 1 -
       0x67 => op_bit_test!(self, reg_a, 4),
       // 0x68 | BIT 5,B | 2 | 8 | Z 0 1 -
@@ -1373,8 +1624,8 @@ impl Emu {
       0x6c => op_bit_test!(self, reg_h, 5),
       // 0x6d | BIT 5,L | 2 | 8 | Z 0 1 -
       0x6d => op_bit_test!(self, reg_l, 5),
-      // 0x6e | BIT 5,(HL) | 2 | 16 | Z 0 1 -
-      0x6e => unimplemented!("Prefix opcode 0x6e is not yet implemented"),
+      // 0x6e | BIT 5,(HL) | 2 | 12 | Z 0 1 -
+      0x6e => op_bit_test_hl!(self, 5),
       // 0x6f | BIT 5,A | 2 | 8 | Z 0 1 -
       0x6f => op_bit_test!(self, reg_a, 5),
       // 0x70 | BIT 6,B | 2 | 8 | Z 0 1 -
@@ -1389,8 +1640,8 @@ impl Emu {
       0x74 => op_bit_test!(self, reg_h, 6),
       // 0x75 | BIT 6,L | 2 | 8 | Z 0 1 -
       0x75 => op_bit_test!(self, reg_l, 6),
-      // 0x76 | BIT 6,(HL) | 2 | 16 | Z 0 1 -
-      0x76 => unimplemented!("Prefix opcode 0x76 is not yet implemented"),
+      // 0x76 | BIT 6,(HL) | 2 | 12 | Z 0 1 -
+      0x76 => op_bit_test_hl!(self, 6),
       // 0x77 | BIT 6,A | 2 | 8 | Z 0 1 -
       0x77 => op_bit_test!(self, reg_a, 6),
       // 0x78 | BIT 7,B | 2 | 8 | Z 0 1 -
@@ -1405,8 +1656,8 @@ impl Emu {
       0x7c => op_bit_test!(self, reg_h, 7),
       // 0x7d | BIT 7,L | 2 | 8 | Z 0 1 -
       0x7d => op_bit_test!(self, reg_l, 7),
-      // 0x7e | BIT 7,(HL) | 2 | 16 | Z 0 1 -
-      0x7e => unimplemented!("Prefix opcode 0x7e is not yet implemented"),
+      // 0x7e | BIT 7,(HL) | 2 | 12 | Z 0 1 -
+      0x7e => op_bit_test_hl!(self, 7),
       // 0x7f | BIT 7,A | 2 | 8 | Z 0 1 -
       0x7f => op_bit_test!(self, reg_a, 7),
       // 0x80 | RES 0,B | 2 | 8 | - - - -
@@ -1721,85 +1972,119 @@ impl Emu {
   fn read_word(&self, addr: u16, force_read: bool) -> u8 {
     debug!("Read word from: 0x{:x}", addr);
 
-    match addr {
-      0x0000...0x3fff => match addr {
-        0x0000...0x00ff => {
-          if self.internal_rom_disabled {
-            self.rom[addr as usize]
-          } else {
-            self.dmg_rom[addr as usize]
-          }
-        }
-        _ => self.rom[addr as usize],
-      },
-      0x4000...0x7fff => {
-        let calculated_rom_address: usize =
-          ((self.rom_bank_number().wrapping_sub(1) as u16 * 0x4000u16) + addr) as usize;
-        self.rom[calculated_rom_address]
-      }
-      0xfe00...0xfe9f | 0x8000...0x9fff | 0xff40...0xff6a => {
+    let result = match addr {
+      0x0000...0x00ff if !self.internal_rom_disabled => Ok(self.dmg_rom[addr as usize]),
+      0x0000...0x7fff | 0xa000...0xbfff => self.cartridge.read_word(addr),
+      0xfe00...0xfe9f | 0x8000...0x9fff | 0xff40...0xff45 | 0xff47...0xff4b => {
         self.graphics.read_word(addr, force_read)
       }
-      _ => self.mem.read_word(addr),
-    }
+      0xff00 => self.input.read_word(addr),
+      0xff01 | 0xff02 => self.serial.read_word(addr),
+      0xff04...0xff07 => self.timer.read_word(addr),
+      0xff0f | 0xffff => self.interrupts.read_word(addr),
+      0xff10...0xff3f => self.sound.read_word(addr),
+      _ => Ok(self.mem.read_word(addr)),
+    };
+
+    // The open bus reads as 0xff.
+    result.unwrap_or_else(|UnmappedAddress(addr)| {
+      self.report_error(EmuError::UnmappedRead {
+        addr,
+        pc: self.instruction_pc,
+        cpu: self.cpu,
+      });
+      0xff
+    })
   }
 
   fn write_word(&mut self, addr: u16, w: u8) {
-    match addr {
-      0x2000...0x3fff => {
-        assert!(w <= 0x1f);
-        self.rom_bank_number = w;
+    let result = match addr {
+      0x0000...0x7fff => self.cartridge.write_word(addr, w),
+      0xff50 => {
+        self.internal_rom_disabled = true;
+        Ok(())
       }
-      0xff50 => self.internal_rom_disabled = true,
       0x8000...0x9fff => {
         // Video ram
-        self.graphics.write_word(addr, w);
+        self.graphics.write_word(addr, w)
       }
-      0xa000...0xbfff => self.mem.write_word(addr, w),
+      0xa000...0xbfff => self.cartridge.write_word(addr, w),
       0xc000...0xcfff => self.mem.write_word(addr, w),
       0xd000...0xdfff => {
         // In DMG this is non switchable.
-        self.mem.write_word(addr, w);
+        self.mem.write_word(addr, w)
       }
+      // Echo of 0xc000 - 0xddff.
+      0xe000...0xfdff => self.mem.write_word(addr, w),
       0xfe00...0xfe9f => self.graphics.write_word(addr, w),
       0xfea0...0xfeff => {
         // dbg!("write to 0xfea0...0xfeff - bug???");
+        Ok(())
       }
-      0xff80...0xffff => {
+      0xffff => self.interrupts.write_word(addr, w),
+      0xff80...0xfffe => {
         // Internal ram
-        self.mem.write_word(addr, w);
+        self.mem.write_word(addr, w)
       }
       0xff00...0xff7f => {
         // i/o ports ---> THIS NEEDS SPECIAL CARE
         match addr {
-          0xff00 => self.input.write_word(addr, w),
+          0xff00 => {
+            let result = self.input.write_word(addr, w);
+            self.handle_input();
+            result
+          }
           0xff01 => self.serial.write_word(addr, w),
           0xff02 => self.serial.write_word(addr, w),
           0xff04...0xff07 => self.timer.write_word(addr, w),
-          0xff0f => self.mem.write_word(addr, w),
+          0xff0f => self.interrupts.write_word(addr, w),
           0xff10...0xff3f => self.sound.write_word(addr, w),
           0xff46 => {
-            self.graphics.dma_request(w, &self.mem);
+            self.dma_transfer(w);
             self.cycles += 160;
+            Ok(())
           }
-          0xff40...0xff6b => self.graphics.write_word(addr, w),
-          0xff7f => { /* seems a bug in tetris, let it go  */ }
-          _ => unimplemented!(
-            "Unimplemented IO port: 0x{:>04x}\n{:?}",
-            addr,
-            self.debugger.as_ref().map(|dbgr| dbgr.dump(&self))
-          ),
-        };
-      }
-      _ => {
-        unimplemented!(
-          "Write on unknown address: 0x{:x} the value: 0x{:x}\n{:#x?}",
-          addr,
-          w,
-          self.cpu
-        );
+          0xff40...0xff45 | 0xff47...0xff4b => self.graphics.write_word(addr, w),
+          _ => {
+            // Unused registers ignore writes (eg. 0xff4d, written by DMG games checking for a CGB, or 0xff7f by
+            // Tetris).
+            debug!(
+              "Write of 0x{:>02x} to unused IO register 0x{:>04x} is ignored.",
+              w, addr
+            );
+            Ok(())
+          }
+        }
       }
+    };
+
+    if let Err(UnmappedAddress(addr)) = result {
+      self.report_error(EmuError::UnmappedWrite {
+        addr,
+        value: w,
+        pc: self.instruction_pc,
+        cpu: self.cpu,
+      });
+    }
+  }
+
+  // OAM DMA, copies 0xa0 bytes from the page through the bus, so the source can be any memory (eg. cartridge ROM
+  // and RAM).
+  fn dma_transfer(&mut self, page: u8) {
+    let source = dword!(page, 0x00);
+
+    let mut data = [0; 0xa0];
+    for (i, value) in data.iter_mut().enumerate() {
+      // @TODO Check if we have to force read (and defent against video phase blocks).
+      *value = self.read_word(source + i as u16, true);
     }
+    self.graphics.dma_request(&data);
+  }
+
+  // Only the first error of the step is kept.
+  fn report_error(&self, err: EmuError) {
+    let first = self.error.take().unwrap_or(err);
+    self.error.set(Some(first));
   }
 
   pub fn read_opcode_word(&mut self) -> u8 {
@@ -1813,9 +2098,21 @@ impl Emu {
     ((hi as u16) << 0x8) | lo as u16
   }
 
-  fn read_dmg_rom(&mut self) {
-    let mut rom_file = File::open("asset/dmg_rom.bin").unwrap();
-    let _ = rom_file.read_to_end(&mut self.dmg_rom).unwrap();
+  // Restarts the machine from the boot ROM instead of the post-boot state.
+  pub fn load_boot_rom(&mut self, path: &str) -> io::Result<()> {
+    let mut dmg_rom = Vec::new();
+    File::open(path)?.read_to_end(&mut dmg_rom)?;
+
+    if dmg_rom.len() != 0x100 {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Boot ROM has to be 256 bytes, got {}", dmg_rom.len()),
+      ));
+    }
+
+    self.dmg_rom = dmg_rom;
+    self.reset();
+    Ok(())
   }
 
   pub fn push_word(&mut self, w: u8) {
@@ -1847,80 +2144,254 @@ impl Emu {
     self.sound.reset();
     self.graphics.reset();
     self.timer.reset();
-    self.interrupts_enabled = false;
-  }
+    self.serial.reset();
+    self.input.reset();
+    self.interrupts.reset();
 
-  fn interrupt_enabled_v_blank(&self) -> bool {
-    bitn!(self.read_word(0xffff, false), 0) == 0x1
+    if self.dmg_rom.is_empty() {
+      self.reset_post_boot();
+    }
   }
 
-  fn interrupt_enabled_lcd_stat(&self) -> bool {
-    bitn!(self.read_word(0xffff, false), 1) == 0x1
+  // Without a boot ROM the execution starts at the cartridge entry point with the state the boot ROM would leave.
+  fn reset_post_boot(&mut self) {
+    self.internal_rom_disabled = true;
+
+    self
+      .cpu
+      .reset_post_boot(self.cartridge.header.header_checksum);
+    self.timer.reset_post_boot();
+    self.sound.reset_post_boot();
+    self.graphics.reset_post_boot();
+    self.interrupts.reset_post_boot();
   }
 
-  fn interrupt_enabled_timer(&self) -> bool {
-    bitn!(self.read_word(0xffff, false), 2) == 0x1
+  pub fn mute_sound(&mut self) {
+    self.sound.mute();
   }
 
-  fn interrupt_enabled_serial(&self) -> bool {
-    bitn!(self.read_word(0xffff, false), 3) == 0x1
+  pub fn is_sound_muted(&self) -> bool {
+    self.sound.is_muted()
   }
+}
 
-  fn interrupt_enabled_joypad(&self) -> bool {
-    bitn!(self.read_word(0xffff, false), 4) == 0x1
-  }
+// Code can run from anywhere holding memory (including VRAM and cartridge RAM), the unusable area and the IO registers
+// can't hold code, there the emulation stops instead of running garbage.
+fn is_pc_executable(pc: u16) -> bool {
+  !(0xfea0..0xff80).contains(&pc)
+}
 
-  fn interrupt_flag_v_blank(&self) -> bool {
-    bitn!(self.read_word(0xff0f, false), 0) == 0x1
-  }
+// Runs the code from 0x0150, after the cartridge header.
+#[cfg(test)]
+fn emu_with_code(code: &[u8]) -> Emu {
+  let mut rom = vec![0; 0x8000];
+  rom[0x0150..0x0150 + code.len()].copy_from_slice(code);
+  let mut emu = Emu::from_bytes(rom).unwrap();
+  emu.cpu.pc = 0x0150;
+  emu
+}
 
-  fn interrupt_flag_lcd_stat(&self) -> bool {
-    bitn!(self.read_word(0xff0f, false), 1) == 0x1
-  }
+#[test]
+fn test_run_frames_serial_output() {
+  let mut emu = emu_with_code(&[
+    0x3e, b'A', // LD A,'A'
+    0xe0, 0x01, // LDH (SB),A
+    0x3e, 0x81, // LD A,0x81
+    0xe0, 0x02, // LDH (SC),A
+    0x18, 0xfe, // JR -2
+  ]);
+
+  emu.run_frames(2).unwrap();
+  assert_eq!(2, emu.frame_count());
+  assert_eq!(b"A", emu.serial_output());
+}
 
-  fn interrupt_flag_timer(&self) -> bool {
-    bitn!(self.read_word(0xff0f, false), 2) == 0x1
-  }
+#[test]
+fn test_stack() {
+  let mut emu = Emu::from_bytes(vec![0; 0x8000]).unwrap();
+  emu.push_dword(0xabcd);
+  assert_eq!(0xabcd, emu.pop_dword());
+}
 
-  fn interrupt_flag_serial(&self) -> bool {
-    bitn!(self.read_word(0xff0f, false), 3) == 0x1
+#[cfg(test)]
+fn step_instructions(emu: &mut Emu, count: usize) {
+  for _ in 0..count {
+    emu.step_instruction().unwrap();
   }
+}
 
-  fn interrupt_flag_joypad(&self) -> bool {
-    bitn!(self.read_word(0xff0f, false), 4) == 0x1
-  }
+#[test]
+fn test_halt_wake_without_ime() {
+  let mut emu = emu_with_code(&[
+    0xf3, // DI
+    0x3e, 0x04, // LD A,0x04
+    0xe0, 0xff, // LDH (IE),A
+    0x76, // HALT
+    0x04, // INC B
+    0x18, 0xfe, // JR -2
+  ]);
+
+  step_instructions(&mut emu, 5);
+  assert!(emu.is_halted);
+  assert_eq!(0x0156, emu.cpu.pc);
+
+  // Woken up by the pending timer interrupt, which is not serviced.
+  emu.write_memory(0xff0f, 0x04);
+  step_instructions(&mut emu, 1);
+  assert!(!emu.is_halted);
+  assert_eq!(0x01, emu.cpu.reg_b);
+  assert_eq!(0x0157, emu.cpu.pc);
+  assert_eq!(0x04, emu.read_memory(0xff0f) & 0x04);
+}
 
-  fn mem_debug_print(&self, addr: u16, len: usize) {
-    for offs in 0..len {
-      if offs % 8 == 0 {
-        print!("\n[0x{:>04x}] ", (addr + offs as u16));
-      }
+#[test]
+fn test_halt_wake_with_ime() {
+  let mut emu = emu_with_code(&[
+    0x3e, 0x04, // LD A,0x04
+    0xe0, 0xff, // LDH (IE),A
+    0xfb, // EI
+    0x76, // HALT
+    0x00, // NOP
+  ]);
+
+  step_instructions(&mut emu, 5);
+  assert!(emu.is_halted);
+
+  // The interrupt is dispatched, returning after HALT.
+  emu.write_memory(0xff0f, 0x04);
+  step_instructions(&mut emu, 1);
+  assert!(!emu.is_halted);
+  assert_eq!(0x0050, emu.cpu.pc);
+  assert_eq!(0x0156, emu.pop_dword());
+  assert_eq!(0x00, emu.read_memory(0xff0f) & 0x04);
+}
 
-      if offs % 4 == 0 {
-        print!(" ");
-      }
+#[test]
+fn test_halt_bug() {
+  let mut emu = emu_with_code(&[
+    0xf3, // DI
+    0x3e, 0x04, // LD A,0x04
+    0xe0, 0xff, // LDH (IE),A
+    0x76, // HALT
+    0x04, // INC B
+    0x00, // NOP
+  ]);
+  emu.write_memory(0xff0f, 0x04);
+
+  // HALT with IME=0 and a pending interrupt is skipped, the next byte is read twice.
+  step_instructions(&mut emu, 4);
+  assert!(!emu.is_halted);
+  step_instructions(&mut emu, 2);
+  assert_eq!(0x02, emu.cpu.reg_b);
+  assert_eq!(0x0157, emu.cpu.pc);
+}
 
-      print!("{:>02x} ", self.read_word(addr + offs as u16, true));
-    }
+#[test]
+fn test_stop_wake_on_joypad() {
+  let mut emu = emu_with_code(&[
+    0x10, 0x00, // STOP 0
+    0x04, // INC B
+  ]);
+
+  step_instructions(&mut emu, 1);
+  assert!(emu.is_stopped());
+  assert_eq!(0, emu.step_instruction().unwrap());
+  assert_eq!(0x0152, emu.cpu.pc);
+
+  emu.set_buttons(Buttons {
+    a: true,
+    ..Buttons::default()
+  });
+  assert!(!emu.is_stopped());
+  step_instructions(&mut emu, 1);
+  assert_eq!(0x01, emu.cpu.reg_b);
+}
 
-    println!("");
+#[test]
+fn test_prefix_instructions() {
+  // Opcode, operand, F, result, F after, cycles. The operand is A, or (HL) for the opcodes ending in 6 or e.
+  #[rustfmt::skip]
+  let cases: [(u8, u8, u8, u8, u8, u64); 22] = [
+    (0x07, 0x85, 0x00, 0x0b, 0x10, 8),  // RLC A
+    (0x0f, 0x01, 0x00, 0x80, 0x10, 8),  // RRC A
+    (0x17, 0x80, 0x00, 0x00, 0x90, 8),  // RL A
+    (0x1f, 0x01, 0x10, 0x80, 0x10, 8),  // RR A
+    (0x27, 0xff, 0x00, 0xfe, 0x10, 8),  // SLA A
+    (0x2f, 0x81, 0x00, 0xc0, 0x10, 8),  // SRA A
+    (0x37, 0xf0, 0x10, 0x0f, 0x00, 8),  // SWAP A
+    (0x37, 0x00, 0x00, 0x00, 0x80, 8),  // SWAP A
+    (0x3f, 0x01, 0x00, 0x00, 0x90, 8),  // SRL A
+    (0x7f, 0x80, 0x00, 0x80, 0x20, 8),  // BIT 7,A
+    (0x06, 0x80, 0x00, 0x01, 0x10, 16), // RLC (HL)
+    (0x0e, 0x00, 0x00, 0x00, 0x80, 16), // RRC (HL)
+    (0x16, 0x40, 0x10, 0x81, 0x00, 16), // RL (HL)
+    (0x1e, 0x02, 0x00, 0x01, 0x00, 16), // RR (HL)
+    (0x26, 0x80, 0x00, 0x00, 0x90, 16), // SLA (HL)
+    (0x2e, 0x80, 0x00, 0xc0, 0x00, 16), // SRA (HL)
+    (0x36, 0x12, 0x00, 0x21, 0x00, 16), // SWAP (HL)
+    (0x3e, 0x80, 0x00, 0x40, 0x00, 16), // SRL (HL)
+    (0x46, 0x01, 0x10, 0x01, 0x30, 12), // BIT 0,(HL)
+    (0x7e, 0x7f, 0x00, 0x7f, 0xa0, 12), // BIT 7,(HL)
+    (0x86, 0x01, 0x10, 0x00, 0x10, 16), // RES 0,(HL)
+    (0xfe, 0x00, 0x00, 0x80, 0x00, 16), // SET 7,(HL)
+  ];
+
+  for &(opcode, operand, f, result, f_after, cycles) in cases.iter() {
+    let mut emu = emu_with_code(&[0xcb, opcode]);
+    emu.cpu.reg_a = operand;
+    emu.cpu.reg_f = f;
+    emu.cpu.set_hl(0xc000);
+    emu.write_memory(0xc000, operand);
+
+    let name = format!("CB 0x{:>02x}", opcode);
+    assert_eq!(cycles, emu.step_instruction().unwrap(), "{}", name);
+    let value = if opcode & 0b111 == 0b110 {
+      emu.read_memory(0xc000)
+    } else {
+      emu.cpu.reg_a
+    };
+    assert_eq!(result, value, "{}", name);
+    assert_eq!(f_after, emu.cpu.reg_f, "{}", name);
   }
+}
 
-  pub fn mute_sound(&mut self) {
-    self.sound.mute();
-  }
+#[test]
+fn test_dma_from_cartridge_ram() {
+  let mut rom = vec![0; 0x8000];
+  // ROM+RAM, 8 KB of RAM.
+  rom[0x0147] = 0x08;
+  rom[0x0149] = 0x02;
+  let mut emu = Emu::from_bytes(rom).unwrap();
+
+  emu.write_memory(0xa000, 0x42);
+  emu.write_memory(0xa09f, 0x43);
+  emu.write_memory(0xff46, 0xa0);
+  assert_eq!(0x42, emu.read_memory(0xfe00));
+  assert_eq!(0x43, emu.read_memory(0xfe9f));
+}
 
-  fn rom_bank_number(&self) -> u8 {
-    match self.rom_bank_number {
-      0 => 1,
-      i => i,
-    }
-  }
+#[test]
+fn test_echo_ram_write() {
+  let mut emu = Emu::from_bytes(vec![0; 0x8000]).unwrap();
+  emu.write_memory(0xe010, 0x42);
+  assert_eq!(0x42, emu.read_memory(0xc010));
+  assert!(emu.error.take().is_none());
 }
 
 #[test]
-fn test_stack() {
-  let mut emu = Emu::new("".to_owned());
-  emu.push_dword(0xabcd);
-  assert_eq!(0xabcd, emu.pop_dword());
+fn test_unused_io_write() {
+  let mut emu = Emu::from_bytes(vec![0; 0x8000]).unwrap();
+  emu.write_memory(0xff4d, 0x01);
+  emu.write_memory(0xff7f, 0x01);
+  assert!(emu.error.take().is_none());
+}
+
+#[test]
+fn test_pc_executable() {
+  assert!(is_pc_executable(0x0100));
+  assert!(is_pc_executable(0x8800));
+  assert!(is_pc_executable(0xa000));
+  assert!(is_pc_executable(0xff80));
+  assert!(!is_pc_executable(0xfea0));
+  assert!(!is_pc_executable(0xff44));
 }
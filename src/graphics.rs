@@ -1,12 +1,14 @@
 use super::display_adapter::*;
 use super::mem::*;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::WindowCanvas;
-use sdl2::Sdl;
-use std::rc::Rc;
-use std::thread::sleep_ms;
-use std::time::SystemTime;
+use super::palette::*;
+use super::sprite::*;
+use super::util::*;
+
+// Durations in t-cycles.
+const LINE_DURATION: u64 = 456;
+const FRAME_DURATION: u64 = LINE_DURATION * 154;
+const OAM_SCAN_DURATION: u64 = 80;
+const MODE3_MIN_DURATION: u64 = 172;
 
 pub enum WindowTileMapDisplayRegion {
   Region_0x9800_0x9BFF,
@@ -28,40 +30,12 @@ enum ObjectSpriteSize {
   Size8x16,
 }
 
-pub enum GdbColor {
-  C0,
-  C1,
-  C2,
-  C3,
-}
-
-impl GdbColor {
-  pub fn as_sdl_color(&self) -> Color {
-    match self {
-      GdbColor::C3 => Color::RGB(0, 0, 0),
-      GdbColor::C2 => Color::RGB(85, 85, 85),
-      GdbColor::C1 => Color::RGB(170, 170, 170),
-      GdbColor::C0 => Color::RGB(255, 255, 255),
-    }
-  }
-}
-
-#[derive(Default, Debug)]
-struct Point {
-  x: usize,
-  y: usize,
-}
-
-impl Point {
-  fn new(x: usize, y: usize) -> Point {
-    Point { x, y }
-  }
-}
-
 #[derive(Debug, Default)]
 pub struct GraphicsUpdateResult {
   pub vblank_interrupt_generated: bool,
   pub lcd_stat_interrupt_generated: bool,
+  // A complete frame is in the framebuffer.
+  pub frame_ready: bool,
 }
 
 pub struct Graphics {
@@ -75,33 +49,30 @@ pub struct Graphics {
   obp1: u8,
   ly_lcdc_y_coordinate: u8,
   lyc: u8,
-  display: ConsoleDisplay,
   mode_timer: u64,
   line: u8,
+  // Internal line counter of the window, independent of LY.
+  window_line: u8,
   stat: u8,
   pub vmem: [u8; 0x2000],
   oam: [u8; 0xa0],
-  canvas: WindowCanvas,
-  fps_timer: SystemTime,
+  // Sprites found by the OAM scan for the current line, in drawing priority order.
+  line_sprites: Vec<Sprite>,
+  mode3_duration: u64,
+  stat_irq_line: bool,
+  stat_interrupt_requested: bool,
+  framebuffer: Framebuffer,
 }
 
 impl Graphics {
-  pub fn new(sdl: Rc<Sdl>) -> Graphics {
-    let video_subsystem = sdl.video().unwrap();
-    let window = video_subsystem
-      .window(
-        "Y.A.G.B.E.",
-        160 * Graphics::scale() as u32,
-        144 * Graphics::scale() as u32,
-      )
-      .position(16, 64)
-      .opengl()
-      .build()
-      .unwrap();
-
+  pub fn new() -> Graphics {
     Graphics {
       vmem: [0; 0x2000],
       oam: [0; 0xa0],
+      line_sprites: Vec::with_capacity(SPRITES_PER_LINE),
+      mode3_duration: MODE3_MIN_DURATION,
+      stat_irq_line: false,
+      stat_interrupt_requested: false,
       lcdc: 0,
       scx: 0,
       scy: 0,
@@ -112,12 +83,11 @@ impl Graphics {
       obp1: 0,
       ly_lcdc_y_coordinate: 0,
       lyc: 0,
-      display: Default::default(),
       mode_timer: 0,
       line: 0,
+      window_line: 0,
       stat: 0,
-      canvas: window.into_canvas().build().unwrap(),
-      fps_timer: SystemTime::now(),
+      framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
     }
   }
 
@@ -125,16 +95,20 @@ impl Graphics {
     // Not sure if this should be set even when reading the DMGROM. If it's on the Nintendo logo is loaded incorrectly
     // due to vmem not being accessible.
     // self.lcdc = 0x91;
-    self.canvas.set_draw_color(Color::RGB(0, 0, 0));
-    self.canvas.clear();
-    self.canvas.present();
+    self.framebuffer = [0; SCREEN_WIDTH * SCREEN_HEIGHT];
+  }
+
+  pub fn reset_post_boot(&mut self) {
+    self.set_lcdc(0x91);
+    self.bgp = 0xfc;
+    self.set_stat_mode(0b10);
   }
 
   fn is_screen_on(&self) -> bool {
     bitn!(self.lcdc, 0x7) == 0b1
   }
 
-  pub fn write_word(&mut self, addr: u16, w: u8) {
+  pub fn write_word(&mut self, addr: u16, w: u8) -> Result<(), UnmappedAddress> {
     let stat_mode = self.stat_mode();
     assert!(stat_mode <= 0b11);
 
@@ -155,33 +129,37 @@ impl Graphics {
         }
       }
       0xff40 => self.set_lcdc(w),
-      0xff41 => self.stat = (self.stat & 0b111) | (w & 0b1111_1000),
+      0xff41 => {
+        self.stat = (self.stat & 0b111) | (w & 0b0111_1000);
+        self.update_stat_irq_line();
+      }
       0xff42 => self.scy = w,
       0xff43 => self.scx = w,
       0xff44 => self.ly_lcdc_y_coordinate = 0x0,
+      0xff45 => {
+        self.lyc = w;
+        self.update_stat_irq_line();
+      }
       0xff47 => self.bgp = w,
       0xff48 => self.obp0 = w,
       0xff49 => self.obp1 = w,
       0xff4a => self.wy = w,
       0xff4b => self.wx = w,
-      _ => unimplemented!("Unknown video address: 0x{:>04x}", addr),
+      _ => return Err(UnmappedAddress(addr)),
     };
+    Ok(())
   }
 
-  pub fn dma_request(&mut self, addr: u8, mem: &Mem) {
-    let addr = dword!(addr, 0x00);
-
-    for i in 0..0xA0 {
-      // @TODO Check if we have to force read (and defent against video phase blocks).
-      self.oam[i] = mem.read_word(addr + i as u16);
-    }
+  // The source is read by the emulator through the memory bus.
+  pub fn dma_request(&mut self, data: &[u8; 0xa0]) {
+    self.oam = *data;
   }
 
-  pub fn read_word(&self, addr: u16, force_read: bool) -> u8 {
+  pub fn read_word(&self, addr: u16, force_read: bool) -> Result<u8, UnmappedAddress> {
     let stat_mode = self.stat_mode();
     assert!(stat_mode <= 0b11);
 
-    match addr {
+    let value = match addr {
       0xfe00...0xfe9f => {
         // Sprite attribute table (OAM).
         if force_read || stat_mode == 0b00 || stat_mode == 0b01 || !self.is_screen_on() {
@@ -192,7 +170,7 @@ impl Graphics {
         }
       }
       0x8000...0x9fff => {
-        if !force_read && stat_mode == 0b11 && !self.is_screen_on() {
+        if !force_read && stat_mode == 0b11 && self.is_screen_on() {
           debug!("VMEM read is ignored.");
           0xff
         } else {
@@ -200,155 +178,261 @@ impl Graphics {
         }
       }
       0xff40 => self.lcdc,
-      0xff41 => self.stat,
+      // Bit 7 is unused and always reads as 1.
+      0xff41 => self.stat | 0b1000_0000,
       0xff42 => self.scy,
       0xff43 => self.scx,
       0xff44 => self.ly_lcdc_y_coordinate,
+      0xff45 => self.lyc,
       0xff47 => self.bgp,
-      _ => unimplemented!("Unrecognized video address: 0x{:>04x}", addr),
-    }
+      0xff48 => self.obp0,
+      0xff49 => self.obp1,
+      0xff4a => self.wy,
+      0xff4b => self.wx,
+      _ => return Err(UnmappedAddress(addr)),
+    };
+    Ok(value)
   }
 
   pub fn update(&mut self, cycles_prev: u64, cycles: u64) -> GraphicsUpdateResult {
     assert!(cycles_prev < cycles);
     let mut response = GraphicsUpdateResult::default();
 
-    // if !self.is_screen_on() {
-    //   return response;
-    // }
-
     self.mode_timer += cycles - cycles_prev;
 
-    match self.stat_mode() {
-      0b10 => {
-        // Accessing OAM.
-        if self.mode_timer >= 80 {
-          self.mode_timer = self.mode_timer % 80;
+    if !self.is_screen_on() {
+      // LY and the mode are frozen, the blank screen is still reported once per frame period.
+      if self.mode_timer >= FRAME_DURATION {
+        self.mode_timer %= FRAME_DURATION;
+        response.frame_ready = true;
+      }
+
+      response.lcd_stat_interrupt_generated = self.take_stat_interrupt();
+      return response;
+    }
+
+    // Big steps (eg. DMA) can cover multiple modes.
+    loop {
+      let mode_duration = match self.stat_mode() {
+        0b10 => OAM_SCAN_DURATION,
+        0b11 => self.mode3_duration,
+        0b00 => LINE_DURATION - OAM_SCAN_DURATION - self.mode3_duration,
+        // 0b01, the mode has 2 bits.
+        _ => LINE_DURATION,
+      };
+
+      if self.mode_timer < mode_duration {
+        break;
+      }
+      self.mode_timer -= mode_duration;
+
+      match self.stat_mode() {
+        0b10 => {
+          // Accessing OAM.
+          self.line_sprites = scan_line(&self.oam, self.line, self.sprite_height());
+          self.mode3_duration = self.calculate_mode3_duration();
           self.set_stat_mode(0b11);
         }
-      }
-      0b11 => {
-        // Accessing VRAM.
-        // TODO  mode 3 about 170-240 tstates depending on where exactly the sprites, window, and fine scroll (SCX modulo 8) are positioned
-        // !!! Draw should happen here !!!
-        if self.mode_timer >= 172 {
-          // HERE DRAW LINE <self.line> from backstage.
+        0b11 => {
+          // Accessing VRAM.
           self.draw_hline(self.line);
-
-          self.mode_timer = self.mode_timer % 172;
           self.set_stat_mode(0b00);
         }
-      }
-      0b00 => {
-        // Horizontal blank.
-        if self.mode_timer >= 204 {
-          self.mode_timer = self.mode_timer % 204;
-
-          self.line += 1;
-          self.ly_lcdc_y_coordinate = self.line;
+        0b00 => {
+          // Horizontal blank.
+          self.set_line(self.line + 1);
 
           if self.line == 144 {
-            // This was 143 but seems we need all 0-143 to be accessible in state 0b11.
-            self.canvas.present();
-
-            let timeframe = SystemTime::now()
-              .duration_since(self.fps_timer)
-              .unwrap()
-              .subsec_millis();
-            // let fps = 1000 / timeframe;
-            self.fps_timer = SystemTime::now();
-            // dbg!(timeframe);
-
-            if timeframe < 16 {
-              sleep_ms(16 - timeframe);
-            }
-
             self.set_stat_mode(0b01);
-            // TODO Possibly do something on screen .. http://imrannazar.com/GameBoy-Emulation-in-JavaScript:-GPU-Timings
-            // Possibly not.
             response.vblank_interrupt_generated = true;
+            response.frame_ready = true;
           } else {
             self.set_stat_mode(0b10);
           }
         }
-      }
-      0b01 => {
-        self.line = 144 + (self.mode_timer / 456) as u8;
-        self.ly_lcdc_y_coordinate = self.line;
-
-        if self.mode_timer >= 4560 {
-          // Vertical blank.
-          self.mode_timer = self.mode_timer % 4560;
-          self.line = 0;
-          self.ly_lcdc_y_coordinate = self.line;
-          self.set_stat_mode(0b10);
+        0b01 => {
+          // Vertical blank, lines 144-153.
+          if self.line == 153 {
+            self.window_line = 0;
+            self.set_line(0);
+            self.set_stat_mode(0b10);
+          } else {
+            self.set_line(self.line + 1);
+          }
         }
+        _ => unreachable!(),
+      }
+    }
+
+    response.lcd_stat_interrupt_generated = self.take_stat_interrupt();
+    response
+  }
+
+  // Mode 3 is longer with fine scrolling, the window and sprites on the line (approximated by the common penalties).
+  fn calculate_mode3_duration(&self) -> u64 {
+    let mut duration = MODE3_MIN_DURATION + (self.scx & 0b111) as u64;
+
+    if self.background_window_display_priority() && self.is_window_visible_on_line(self.line) {
+      duration += 6;
+    }
+
+    if self.object_sprite_display_enable() {
+      for sprite in self.line_sprites.iter() {
+        let offs = (sprite.x + self.scx as i32).rem_euclid(8) as u64;
+        duration += 11 - offs.min(5);
       }
-      mode @ _ => panic!("Illegal LCDC mode: 0b{:b}", mode),
     }
 
-    if self.ly_lcdc_y_coordinate == self.lyc {
-      response.lcd_stat_interrupt_generated = true;
+    duration
+  }
+
+  fn set_line(&mut self, line: u8) {
+    self.line = line;
+    self.ly_lcdc_y_coordinate = line;
+    self.update_stat_irq_line();
+  }
+
+  // The STAT interrupt is requested when any of the enabled sources turns the shared IRQ line high. While it's high
+  // other sources cannot trigger a new interrupt ("STAT blocking").
+  fn update_stat_irq_line(&mut self) {
+    let coincidence = self.ly_lcdc_y_coordinate == self.lyc;
+    self.stat = Util::setbit(self.stat, 2, coincidence.as_bit());
+
+    let mode = self.stat_mode();
+    let irq_line = self.is_screen_on()
+      && ((bitn!(self.stat, 3) == 0x1 && mode == 0b00)
+        || (bitn!(self.stat, 4) == 0x1 && mode == 0b01)
+        || (bitn!(self.stat, 5) == 0x1 && mode == 0b10)
+        || (bitn!(self.stat, 6) == 0x1 && coincidence));
+
+    if irq_line && !self.stat_irq_line {
+      self.stat_interrupt_requested = true;
     }
+    self.stat_irq_line = irq_line;
+  }
 
-    response
+  fn take_stat_interrupt(&mut self) -> bool {
+    let requested = self.stat_interrupt_requested;
+    self.stat_interrupt_requested = false;
+    requested
   }
 
   fn draw_hline(&mut self, line: u8) {
-    // Background.
-    let row = ((line as i32 + self.scy as i32) & 0xff) / 8;
-    let tile_line: i32 = (line as i32 + self.scy as i32) & 0b111;
+    // Raw color codes of the background and window, sprite priority is decided on these.
+    let mut color_codes = [0u8; 160];
+    // Colors after applying the palette registers.
+    let mut shades = [0u8; 160];
+
+    // On DMG bit 0 turns off both the background and the window.
+    if self.background_window_display_priority() {
+      self.draw_background_line(line, &mut color_codes);
+
+      if self.is_window_visible_on_line(line) {
+        self.draw_window_line(&mut color_codes);
+        // The window keeps its own line counter, it only advances on lines where it was drawn.
+        self.window_line += 1;
+      }
 
-    let tile_offs_base: usize = match self.background_tile_map_display_select() {
+      for x in 0..160 {
+        shades[x] = apply_palette_register(self.bgp, color_codes[x]);
+      }
+    }
+
+    if self.object_sprite_display_enable() {
+      self.draw_sprites_line(line, &color_codes, &mut shades);
+    }
+
+    let line_offs = line as usize * SCREEN_WIDTH;
+    self.framebuffer[line_offs..(line_offs + SCREEN_WIDTH)].copy_from_slice(&shades);
+  }
+
+  fn draw_background_line(&self, line: u8, color_codes: &mut [u8; 160]) {
+    let map_y = line.wrapping_add(self.scy);
+    let tile_map_base: usize = match self.background_tile_map_display_select() {
       BackgroundTileMapDisplayRegion::Region_0x9800_0x9BFF => 0x9800 - 0x8000,
       BackgroundTileMapDisplayRegion::Region_0x9C00_0x9FFF => 0x9c00 - 0x8000,
     };
-    let map_region_start: usize = match self.background_and_window_tile_data_select() {
-      BackgroundAndWindowTileDataRegion::Region_0x8000_0x8FFF => 0x8000 - 0x8000,
-      BackgroundAndWindowTileDataRegion::Region_0x8800_0x97FF => 0x8800 - 0x8000,
-    };
 
-    let tile_block_size = 0x10;
+    for x in 0..160 {
+      let map_x = (x as u8).wrapping_add(self.scx);
+      color_codes[x] = self.tile_map_color_code(tile_map_base, map_x, map_y);
+    }
+  }
 
-    for col in 0..32 {
-      let orig_x = col << 3;
-      let virt_x: i32 = (orig_x as i32 + 256 - self.scx as i32) & 0xff;
+  fn is_window_visible_on_line(&self, line: u8) -> bool {
+    self.is_window_display_enabled() && line >= self.wy && self.wx <= 166
+  }
 
-      // The tile line would not be presented on the visible line scan.
-      if virt_x >= 160 {
-        continue;
-      }
+  fn draw_window_line(&self, color_codes: &mut [u8; 160]) {
+    let tile_map_base: usize = match self.window_tile_map_display_select() {
+      WindowTileMapDisplayRegion::Region_0x9800_0x9BFF => 0x9800 - 0x8000,
+      WindowTileMapDisplayRegion::Region_0x9C00_0x9FFF => 0x9c00 - 0x8000,
+    };
+
+    // WX is offset by 7, values below 7 shift the window partially off the screen.
+    let window_start = self.wx as i32 - 7;
+    for x in window_start.max(0)..160 {
+      let map_x = (x - window_start) as u8;
+      color_codes[x as usize] = self.tile_map_color_code(tile_map_base, map_x, self.window_line);
+    }
+  }
 
-      let tile_idx = ((row as usize) << 5) + col;
-      let tile_offs: usize = tile_offs_base + tile_idx;
-      let tile_addr: usize = map_region_start + (self.vmem[tile_offs] as usize * tile_block_size);
+  fn draw_sprites_line(&self, line: u8, color_codes: &[u8; 160], shades: &mut [u8; 160]) {
+    let height = self.sprite_height();
 
-      let pixel_lo = self.vmem[tile_addr + ((tile_line as usize) << 1) + 1];
-      let pixel_hi = self.vmem[tile_addr + ((tile_line as usize) << 1)];
+    for x in 0..160 {
+      // Sprites are in priority order, the first non transparent pixel wins.
+      for sprite in self.line_sprites.iter() {
+        let sprite_x = x as i32 - sprite.x;
+        if !(0..8).contains(&sprite_x) {
+          continue;
+        }
 
-      for i in 0..8 {
-        // Out of screen.
-        if virt_x + i >= 160 {
+        let tile_x = if sprite.is_x_flipped() {
+          7 - sprite_x
+        } else {
+          sprite_x
+        } as u8;
+        let color_code = self.tile_line_color_code(sprite.tile_line_addr(line, height), tile_x);
+        if color_code == 0b00 {
           continue;
         }
 
-        let color_bit_hi = (pixel_hi >> (7 - i)) & 1;
-        let color_bit_lo = (pixel_lo >> (7 - i)) & 1;
-        let color_code = (color_bit_hi << 1) | color_bit_lo;
-        let color = self.color_bit_to_color(color_code);
-        self.set_pixel(color, Point::new((virt_x + i) as usize, line as usize));
+        if !sprite.is_behind_background() || color_codes[x] == 0b00 {
+          let palette = if sprite.uses_obp1() {
+            self.obp1
+          } else {
+            self.obp0
+          };
+          shades[x] = apply_palette_register(palette, color_code);
+        }
+        break;
       }
     }
   }
 
-  pub fn color_bit_to_color(&self, bitmask: u8) -> GdbColor {
-    match bitmask {
-      0b00 => GdbColor::C0,
-      0b01 => GdbColor::C1,
-      0b10 => GdbColor::C2,
-      0b11 => GdbColor::C3,
-      _ => panic!("Invalid color bitmask."),
-    }
+  // Color code (0-3) of a pixel in a 256x256 tile map.
+  fn tile_map_color_code(&self, tile_map_base: usize, map_x: u8, map_y: u8) -> u8 {
+    let tile_idx = ((map_y as usize >> 3) << 5) + (map_x as usize >> 3);
+    let tile_number = self.vmem[tile_map_base + tile_idx];
+
+    let tile_addr = match self.background_and_window_tile_data_select() {
+      BackgroundAndWindowTileDataRegion::Region_0x8000_0x8FFF => tile_number as usize * 0x10,
+      // Signed tile numbers relative to 0x9000.
+      BackgroundAndWindowTileDataRegion::Region_0x8800_0x97FF => {
+        (0x1000 + (tile_number as i8 as i32) * 0x10) as usize
+      }
+    };
+
+    let line_addr = tile_addr + (((map_y & 0b111) as usize) << 1);
+    self.tile_line_color_code(line_addr, map_x & 0b111)
+  }
+
+  // Each tile line is 2 bytes, the first holds the low bits, the second the high bits of the color codes.
+  fn tile_line_color_code(&self, line_addr: usize, tile_x: u8) -> u8 {
+    let color_bit_lo = (self.vmem[line_addr] >> (7 - tile_x)) & 1;
+    let color_bit_hi = (self.vmem[line_addr + 1] >> (7 - tile_x)) & 1;
+    (color_bit_hi << 1) | color_bit_lo
   }
 
   fn stat_mode(&self) -> u8 {
@@ -358,6 +442,7 @@ impl Graphics {
   fn set_stat_mode(&mut self, mode: u8) {
     assert!(mode <= 0b11);
     self.stat = (self.stat & 0b1111_1100) | (mode & 0b11);
+    self.update_stat_irq_line();
   }
 
   fn set_lcdc(&mut self, w: u8) {
@@ -365,19 +450,21 @@ impl Graphics {
     self.lcdc = w;
 
     if bitn!(changed_bits, 7) == 0x1 {
+      self.mode_timer = 0;
+      self.window_line = 0;
+      self.line_sprites.clear();
+
       if bitn!(self.lcdc, 7) == 0x1 {
-        // Turn display on.
-        // Should draw immediately with LY = 0.
-        self.display.on();
+        // Turn display on, starts with LY = 0.
+        self.set_line(0);
+        self.set_stat_mode(0b10);
       } else {
-        // Turn display off.
-        self.display.off();
+        // Turn display off, LY is reset and the mode is reported as HBlank. The screen turns blank.
+        self.framebuffer = [0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        self.set_line(0);
+        self.set_stat_mode(0b00);
       }
     }
-
-    if bitn!(changed_bits, 5) == 0x1 {
-      unimplemented!("Window display enabled bit operation has not been implemented.");
-    }
   }
 
   fn is_window_display_enabled(&self) -> bool {
@@ -416,6 +503,13 @@ impl Graphics {
     }
   }
 
+  fn sprite_height(&self) -> u8 {
+    match self.object_sprite_size() {
+      ObjectSpriteSize::Size8x8 => 8,
+      ObjectSpriteSize::Size8x16 => 16,
+    }
+  }
+
   fn object_sprite_display_enable(&self) -> bool {
     bitn!(self.lcdc, 1) == 0x1
   }
@@ -424,27 +518,154 @@ impl Graphics {
     bitn!(self.lcdc, 0) == 0x1
   }
 
-  pub fn draw_display(&self) {
-    self.display.draw();
+  pub fn framebuffer(&self) -> &Framebuffer {
+    &self.framebuffer
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  // Screen on as the boot ROM leaves it, at the start of line 0.
+  fn graphics_on() -> Graphics {
+    let mut graphics = Graphics::new();
+    graphics.reset_post_boot();
+    graphics
+  }
+
+  // Runs whole lines from the start of `line`.
+  fn run_lines(graphics: &mut Graphics, line: u64, count: u64) {
+    let start = line * LINE_DURATION;
+    graphics.update(start, start + count * LINE_DURATION);
+  }
+
+  #[test]
+  fn test_lyc_coincidence() {
+    let mut graphics = graphics_on();
+    graphics.write_word(0xff45, 2).unwrap();
+    assert_eq!(0b000, graphics.read_word(0xff41, false).unwrap() & 0b100);
+
+    run_lines(&mut graphics, 0, 2);
+    assert_eq!(2, graphics.read_word(0xff44, false).unwrap());
+    assert_eq!(0b100, graphics.read_word(0xff41, false).unwrap() & 0b100);
+
+    run_lines(&mut graphics, 2, 1);
+    assert_eq!(0b000, graphics.read_word(0xff41, false).unwrap() & 0b100);
+  }
+
+  #[test]
+  fn test_stat_interrupt_sources() {
+    // LYC interrupt on line 1.
+    let mut graphics = graphics_on();
+    graphics.write_word(0xff45, 1).unwrap();
+    graphics.write_word(0xff41, 0b0100_0000).unwrap();
+    assert!(!graphics.update(0, 455).lcd_stat_interrupt_generated);
+    assert!(graphics.update(455, 456).lcd_stat_interrupt_generated);
+
+    // Mode 0 interrupt when entering HBlank.
+    let mut graphics = graphics_on();
+    graphics.write_word(0xff41, 0b0000_1000).unwrap();
+    assert!(!graphics.update(0, 251).lcd_stat_interrupt_generated);
+    assert!(graphics.update(251, 252).lcd_stat_interrupt_generated);
+  }
+
+  #[test]
+  fn test_stat_blocking() {
+    let mut graphics = graphics_on();
+    graphics.write_word(0xff45, 1).unwrap();
+    graphics.write_word(0xff41, 0b0100_1000).unwrap();
+
+    assert!(graphics.update(0, 252).lcd_stat_interrupt_generated);
+    // The line is still high from HBlank when LY becomes LYC, no second interrupt.
+    assert!(!graphics.update(252, 457).lcd_stat_interrupt_generated);
+    assert_eq!(1, graphics.read_word(0xff44, false).unwrap());
   }
 
-  fn clear_pixel(&mut self, coord: Point) {
-    self.set_pixel(GdbColor::C0, coord);
+  #[test]
+  fn test_lcd_off() {
+    let mut graphics = graphics_on();
+    run_lines(&mut graphics, 0, 5);
+    assert_eq!(5, graphics.read_word(0xff44, false).unwrap());
+
+    graphics.write_word(0xff40, 0x11).unwrap();
+    assert_eq!(0, graphics.read_word(0xff44, false).unwrap());
+    assert_eq!(0b00, graphics.read_word(0xff41, false).unwrap() & 0b11);
+
+    // Frozen while off.
+    run_lines(&mut graphics, 5, 3);
+    assert_eq!(0, graphics.read_word(0xff44, false).unwrap());
+    assert_eq!(0b00, graphics.read_word(0xff41, false).unwrap() & 0b11);
+
+    graphics.write_word(0xff40, 0x91).unwrap();
+    assert_eq!(0, graphics.read_word(0xff44, false).unwrap());
+    assert_eq!(0b10, graphics.read_word(0xff41, false).unwrap() & 0b11);
   }
 
-  fn set_pixel(&mut self, color: GdbColor, coord: Point) {
-    assert!(coord.x < 160 && coord.y < 144);
+  #[test]
+  fn test_mode3_duration() {
+    let mut graphics = graphics_on();
+    graphics.update(0, OAM_SCAN_DURATION);
+    assert_eq!(MODE3_MIN_DURATION, graphics.mode3_duration);
+
+    // Fine scrolling.
+    let mut graphics = graphics_on();
+    graphics.write_word(0xff43, 3).unwrap();
+    graphics.update(0, OAM_SCAN_DURATION);
+    assert_eq!(MODE3_MIN_DURATION + 3, graphics.mode3_duration);
+
+    // A sprite at X 0 costs 11 cycles, less when it's not aligned with the background tiles.
+    let mut graphics = graphics_on();
+    graphics.write_word(0xff40, 0x93).unwrap();
+    graphics.oam[0..4].copy_from_slice(&[16, 8, 0, 0]);
+    graphics.update(0, OAM_SCAN_DURATION);
+    assert_eq!(MODE3_MIN_DURATION + 11, graphics.mode3_duration);
+
+    let mut graphics = graphics_on();
+    graphics.write_word(0xff40, 0x93).unwrap();
+    graphics.write_word(0xff43, 3).unwrap();
+    graphics.oam[0..4].copy_from_slice(&[16, 8, 0, 0]);
+    graphics.update(0, OAM_SCAN_DURATION);
+    assert_eq!(MODE3_MIN_DURATION + 3 + 8, graphics.mode3_duration);
+  }
+
+  #[test]
+  fn test_window_line_counter() {
+    let mut graphics = graphics_on();
+    // Window enabled at the top left.
+    graphics.write_word(0xff40, 0xb1).unwrap();
+    graphics.write_word(0xff4b, 7).unwrap();
+
+    run_lines(&mut graphics, 0, 2);
+    assert_eq!(2, graphics.window_line);
+
+    // Moved off the screen, the window isn't drawn and the counter stays.
+    graphics.write_word(0xff4b, 167).unwrap();
+    run_lines(&mut graphics, 2, 3);
+    assert_eq!(2, graphics.window_line);
 
-    self.canvas.set_draw_color(color.as_sdl_color());
-    let _ = self.canvas.fill_rect(Rect::new(
-      (coord.x * Graphics::scale()) as i32,
-      (coord.y * Graphics::scale()) as i32,
-      Graphics::scale() as u32,
-      Graphics::scale() as u32,
-    ));
+    graphics.write_word(0xff4b, 7).unwrap();
+    run_lines(&mut graphics, 5, 1);
+    assert_eq!(3, graphics.window_line);
   }
 
-  fn scale() -> usize {
-    2
+  #[test]
+  fn test_window_set_after_ly() {
+    let mut graphics = graphics_on();
+    // Only the first line of tile 0 has color 1.
+    graphics.vmem[0] = 0xff;
+    graphics.write_word(0xff40, 0xb1).unwrap();
+    graphics.write_word(0xff4a, 100).unwrap();
+    graphics.write_word(0xff4b, 7).unwrap();
+
+    run_lines(&mut graphics, 0, 5);
+    assert_eq!(0, graphics.window_line);
+
+    // The window starts with its first line where it's first drawn, not at LY - WY.
+    graphics.write_word(0xff4a, 3).unwrap();
+    run_lines(&mut graphics, 5, 1);
+    assert_eq!(1, graphics.window_line);
+    assert_eq!(3, graphics.framebuffer()[5 * SCREEN_WIDTH]);
+    assert_eq!(0, graphics.framebuffer()[4 * SCREEN_WIDTH]);
   }
 }
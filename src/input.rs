@@ -1,8 +1,396 @@
+use super::mem::UnmappedAddress;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+  Right,
+  Left,
+  Up,
+  Down,
+  A,
+  B,
+  Select,
+  Start,
+}
+
+impl Button {
+  pub const ALL: [Button; 8] = [
+    Button::Right,
+    Button::Left,
+    Button::Up,
+    Button::Down,
+    Button::A,
+    Button::B,
+    Button::Select,
+    Button::Start,
+  ];
+
+  // Name used in the config file.
+  pub fn name(self) -> &'static str {
+    match self {
+      Button::Right => "right",
+      Button::Left => "left",
+      Button::Up => "up",
+      Button::Down => "down",
+      Button::A => "a",
+      Button::B => "b",
+      Button::Select => "select",
+      Button::Start => "start",
+    }
+  }
+
+  pub fn parse(name: &str) -> Option<Button> {
+    Button::ALL
+      .iter()
+      .cloned()
+      .find(|button| button.name() == name)
+  }
+
+  // Bit of the button in the P1 register when its row is selected.
+  fn bit(self) -> u8 {
+    match self {
+      Button::Right | Button::A => 0,
+      Button::Left | Button::B => 1,
+      Button::Up | Button::Select => 2,
+      Button::Down | Button::Start => 3,
+    }
+  }
+
+  fn is_direction(self) -> bool {
+    matches!(
+      self,
+      Button::Right | Button::Left | Button::Up | Button::Down
+    )
+  }
+}
+
+// State of the joypad, true means pressed.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Buttons {
+  pub right: bool,
+  pub left: bool,
+  pub up: bool,
+  pub down: bool,
+  pub a: bool,
+  pub b: bool,
+  pub select: bool,
+  pub start: bool,
+}
+
+impl Buttons {
+  pub fn is_pressed(&self, button: Button) -> bool {
+    match button {
+      Button::Right => self.right,
+      Button::Left => self.left,
+      Button::Up => self.up,
+      Button::Down => self.down,
+      Button::A => self.a,
+      Button::B => self.b,
+      Button::Select => self.select,
+      Button::Start => self.start,
+    }
+  }
+
+  pub fn set(&mut self, button: Button, is_pressed: bool) {
+    match button {
+      Button::Right => self.right = is_pressed,
+      Button::Left => self.left = is_pressed,
+      Button::Up => self.up = is_pressed,
+      Button::Down => self.down = is_pressed,
+      Button::A => self.a = is_pressed,
+      Button::B => self.b = is_pressed,
+      Button::Select => self.select = is_pressed,
+      Button::Start => self.start = is_pressed,
+    }
+  }
+}
+
+// Keyboard key of each button, by SDL key name (eg `Return`, `Left Shift`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBindings {
+  keys: [String; 8],
+}
+
+impl KeyBindings {
+  pub fn key(&self, button: Button) -> &str {
+    &self.keys[button as usize]
+  }
+
+  pub fn set_key(&mut self, button: Button, key: &str) {
+    self.keys[button as usize] = key.to_owned();
+  }
+}
+
+impl Default for KeyBindings {
+  fn default() -> KeyBindings {
+    KeyBindings {
+      keys: [
+        "Right".to_owned(),
+        "Left".to_owned(),
+        "Up".to_owned(),
+        "Down".to_owned(),
+        "X".to_owned(),
+        "Z".to_owned(),
+        "Backspace".to_owned(),
+        "Return".to_owned(),
+      ],
+    }
+  }
+}
+
+const DEFAULT_PAD_DEADZONE: f32 = 0.25;
+
+// Game controller inputs of each button, by SDL game controller button names (eg `a`, `dpup`, `leftshoulder`) and
+// axis names with the direction prefixed (eg `+leftx`, `-lefty`, `+righttrigger`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PadBindings {
+  inputs: [Vec<String>; 8],
+  // Fraction of the axis range ignored around the center.
+  pub deadzone: f32,
+}
+
+impl PadBindings {
+  pub fn inputs(&self, button: Button) -> &[String] {
+    &self.inputs[button as usize]
+  }
+
+  pub fn apply(&mut self, setting: &PadSetting) {
+    match setting {
+      PadSetting::Deadzone(deadzone) => self.deadzone = *deadzone,
+      PadSetting::Inputs(button, inputs) => self.inputs[*button as usize] = inputs.clone(),
+    }
+  }
+}
+
+impl Default for PadBindings {
+  fn default() -> PadBindings {
+    let inputs = |names: &[&str]| names.iter().map(|&name| name.to_owned()).collect();
+
+    PadBindings {
+      inputs: [
+        inputs(&["dpright", "+leftx"]),
+        inputs(&["dpleft", "-leftx"]),
+        inputs(&["dpup", "-lefty"]),
+        inputs(&["dpdown", "+lefty"]),
+        // By position, A is the right and B is the bottom face button.
+        inputs(&["b"]),
+        inputs(&["a"]),
+        inputs(&["back"]),
+        inputs(&["start"]),
+      ],
+      deadzone: DEFAULT_PAD_DEADZONE,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PadSetting {
+  Deadzone(f32),
+  Inputs(Button, Vec<String>),
+}
+
+impl PadSetting {
+  // The name is `deadzone` or a button name. Inputs are separated by commas.
+  pub fn parse(name: &str, value: &str) -> Result<PadSetting, String> {
+    if name == "deadzone" {
+      return match value.parse::<f32>() {
+        Ok(deadzone) if (0.0..1.0).contains(&deadzone) => Ok(PadSetting::Deadzone(deadzone)),
+        _ => Err(format!("Invalid deadzone: '{}', expected 0.0 - 1.0", value)),
+      };
+    }
+
+    let button = Button::parse(name).ok_or_else(|| format!("Unknown button: '{}'", name))?;
+    let inputs: Vec<String> = value
+      .split(',')
+      .map(|input| input.trim().to_owned())
+      .filter(|input| !input.is_empty())
+      .collect();
+
+    if inputs.is_empty() {
+      return Err(format!("Missing controller input for '{}'", name));
+    }
+
+    Ok(PadSetting::Inputs(button, inputs))
+  }
+}
+
+// Bindings of all controllers with the overrides of specific controllers (by SDL controller name).
+#[derive(Debug, Clone, Default)]
+pub struct PadConfig {
+  pub bindings: PadBindings,
+  pub controller_settings: Vec<(String, PadSetting)>,
+}
+
+impl PadConfig {
+  pub fn bindings_for(&self, controller_name: &str) -> PadBindings {
+    let mut bindings = self.bindings.clone();
+    for (name, setting) in self.controller_settings.iter() {
+      if name == controller_name {
+        bindings.apply(setting);
+      }
+    }
+    bindings
+  }
+}
+
+// P1 (0xff00) joypad register. Bit 4 low selects the direction keys, bit 5 low the action keys, bits 0-3 report the
+// selected keys with 0 meaning pressed.
 #[derive(Default, Debug)]
-pub struct Input;
+pub struct Input {
+  buttons: Buttons,
+  // Select bits (4-5) as written.
+  select: u8,
+  interrupt_requested: bool,
+}
 
 impl Input {
-  pub fn write_word(&self, addr: u16, w: u8) {
-    // @TODO Implement.
+  // Keeps the pressed buttons, they are physical state.
+  pub fn reset(&mut self) {
+    self.select = 0;
+    self.interrupt_requested = false;
+  }
+
+  // Returns true when a button got pressed.
+  pub fn set_buttons(&mut self, buttons: Buttons) -> bool {
+    let has_new_press = Button::ALL
+      .iter()
+      .any(|&button| buttons.is_pressed(button) && !self.buttons.is_pressed(button));
+
+    let lines_prev = self.lines();
+    self.buttons = buttons;
+    self.check_interrupt(lines_prev);
+
+    has_new_press
+  }
+
+  pub fn buttons(&self) -> Buttons {
+    self.buttons
+  }
+
+  pub fn read_word(&self, addr: u16) -> Result<u8, UnmappedAddress> {
+    match addr {
+      // Unused bits read as 1.
+      0xff00 => Ok(0b1100_0000 | self.select | self.lines()),
+      _ => Err(UnmappedAddress(addr)),
+    }
+  }
+
+  pub fn write_word(&mut self, addr: u16, w: u8) -> Result<(), UnmappedAddress> {
+    match addr {
+      0xff00 => {
+        let lines_prev = self.lines();
+        self.select = w & 0b0011_0000;
+        self.check_interrupt(lines_prev);
+        Ok(())
+      }
+      _ => Err(UnmappedAddress(addr)),
+    }
+  }
+
+  // Returns true once after a joypad interrupt was triggered.
+  pub fn take_interrupt(&mut self) -> bool {
+    let interrupt_requested = self.interrupt_requested;
+    self.interrupt_requested = false;
+    interrupt_requested
+  }
+
+  fn is_direction_selected(&self) -> bool {
+    bitn!(self.select, 4) == 0x0
+  }
+
+  fn is_action_selected(&self) -> bool {
+    bitn!(self.select, 5) == 0x0
+  }
+
+  // Input lines P10-P13, active low. With both rows selected a line is low if a key of either row is pressed.
+  fn lines(&self) -> u8 {
+    let mut lines = 0b1111;
+    for &button in Button::ALL.iter() {
+      let is_selected = if button.is_direction() {
+        self.is_direction_selected()
+      } else {
+        self.is_action_selected()
+      };
+
+      if is_selected && self.buttons.is_pressed(button) {
+        lines &= !(1 << button.bit());
+      }
+    }
+    lines
+  }
+
+  // The interrupt is triggered by any input line going from high to low.
+  fn check_interrupt(&mut self, lines_prev: u8) {
+    if lines_prev & !self.lines() != 0 {
+      self.interrupt_requested = true;
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_select_rows() {
+    let mut input = Input::default();
+    input.set_buttons(Buttons {
+      left: true,
+      start: true,
+      ..Buttons::default()
+    });
+
+    input.write_word(0xff00, 0b0010_0000).unwrap();
+    assert_eq!(0b1110_1101, input.read_word(0xff00).unwrap());
+    input.write_word(0xff00, 0b0001_0000).unwrap();
+    assert_eq!(0b1101_0111, input.read_word(0xff00).unwrap());
+    input.write_word(0xff00, 0b0011_0000).unwrap();
+    assert_eq!(0b1111_1111, input.read_word(0xff00).unwrap());
+  }
+
+  #[test]
+  fn test_interrupt() {
+    let mut input = Input::default();
+    input.write_word(0xff00, 0b0010_0000).unwrap();
+
+    // Action keys are not selected.
+    input.set_buttons(Buttons {
+      a: true,
+      ..Buttons::default()
+    });
+    assert!(!input.take_interrupt());
+
+    // Selecting the row pulls the line low.
+    input.write_word(0xff00, 0b0001_0000).unwrap();
+    assert!(input.take_interrupt());
+    assert!(!input.take_interrupt());
+
+    input.set_buttons(Buttons::default());
+    assert!(!input.take_interrupt());
+  }
+
+  #[test]
+  fn test_pad_config() {
+    let mut pad_config = PadConfig::default();
+    pad_config
+      .bindings
+      .apply(&PadSetting::parse("a", "x, y").unwrap());
+    pad_config.controller_settings.push((
+      "Pro Controller".to_owned(),
+      PadSetting::parse("deadzone", "0.5").unwrap(),
+    ));
+
+    let bindings = pad_config.bindings_for("Pro Controller");
+    assert_eq!(
+      &["x".to_owned(), "y".to_owned()],
+      bindings.inputs(Button::A)
+    );
+    assert_eq!(0.5, bindings.deadzone);
+    assert_eq!(
+      DEFAULT_PAD_DEADZONE,
+      pad_config.bindings_for("Other").deadzone
+    );
+
+    assert!(PadSetting::parse("deadzone", "1.5").is_err());
+    assert!(PadSetting::parse("turbo", "x").is_err());
   }
 }
\ No newline at end of file
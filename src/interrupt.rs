@@ -0,0 +1,235 @@
+use super::mem::UnmappedAddress;
+use super::util::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+  VBlank,
+  LcdStat,
+  Timer,
+  Serial,
+  Joypad,
+}
+
+impl Interrupt {
+  // Ordered by priority, VBlank is served first.
+  pub const ALL: [Interrupt; 5] = [
+    Interrupt::VBlank,
+    Interrupt::LcdStat,
+    Interrupt::Timer,
+    Interrupt::Serial,
+    Interrupt::Joypad,
+  ];
+
+  pub fn bit(self) -> u32 {
+    match self {
+      Interrupt::VBlank => 0,
+      Interrupt::LcdStat => 1,
+      Interrupt::Timer => 2,
+      Interrupt::Serial => 3,
+      Interrupt::Joypad => 4,
+    }
+  }
+
+  pub fn vector(self) -> u16 {
+    0x40 + 0x8 * self.bit() as u16
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct InterruptController {
+  ie: u8,
+  iflag: u8,
+  ime: bool,
+  ime_scheduled: bool,
+  serviced_count: [u64; 5],
+  last_serviced: Option<Interrupt>,
+}
+
+impl InterruptController {
+  pub fn reset(&mut self) {
+    *self = InterruptController::default();
+  }
+
+  // State left behind by the DMG boot ROM, VBlank is requested.
+  pub fn reset_post_boot(&mut self) {
+    self.reset();
+    self.iflag = 0b0_0001;
+  }
+
+  pub fn read_word(&self, addr: u16) -> Result<u8, UnmappedAddress> {
+    match addr {
+      // Unused upper bits of IF always read as 1.
+      0xff0f => Ok(self.iflag | 0b1110_0000),
+      0xffff => Ok(self.ie),
+      _ => Err(UnmappedAddress(addr)),
+    }
+  }
+
+  pub fn write_word(&mut self, addr: u16, w: u8) -> Result<(), UnmappedAddress> {
+    match addr {
+      0xff0f => self.iflag = w & 0b1_1111,
+      0xffff => self.ie = w,
+      _ => return Err(UnmappedAddress(addr)),
+    }
+    Ok(())
+  }
+
+  pub fn enabled(&self) -> u8 {
+    self.ie
+  }
+
+  pub fn requested(&self) -> u8 {
+    self.iflag
+  }
+
+  pub fn request(&mut self, interrupt: Interrupt) {
+    self.iflag = Util::setbit(self.iflag, interrupt.bit(), 0x1);
+  }
+
+  // Interrupts that are both requested and enabled, regardless of IME.
+  pub fn pending(&self) -> u8 {
+    self.ie & self.iflag & 0b1_1111
+  }
+
+  pub fn has_pending(&self) -> bool {
+    self.pending() != 0
+  }
+
+  pub fn next_pending(&self) -> Option<Interrupt> {
+    let pending = self.pending();
+    Interrupt::ALL
+      .iter()
+      .find(|interrupt| bitn!(pending, interrupt.bit()) == 0x1)
+      .cloned()
+  }
+
+  pub fn is_master_enabled(&self) -> bool {
+    self.ime
+  }
+
+  // EI - IME is only set after the instruction following EI.
+  pub fn enable_delayed(&mut self) {
+    self.ime_scheduled = true;
+  }
+
+  // RETI - IME is set immediately.
+  pub fn enable(&mut self) {
+    self.ime = true;
+    self.ime_scheduled = false;
+  }
+
+  // DI - also cancels a pending EI.
+  pub fn disable(&mut self) {
+    self.ime = false;
+    self.ime_scheduled = false;
+  }
+
+  // Has to be called once per instruction, after the interrupt check and before the execution.
+  pub fn apply_delayed_enable(&mut self) {
+    if self.ime_scheduled {
+      self.ime = true;
+      self.ime_scheduled = false;
+    }
+  }
+
+  // Picks the highest priority pending interrupt, acknowledges it and disables further interrupts.
+  pub fn service(&mut self) -> Option<Interrupt> {
+    if !self.ime {
+      return None;
+    }
+
+    let interrupt = self.next_pending()?;
+    self.iflag = Util::setbit(self.iflag, interrupt.bit(), 0x0);
+    self.ime = false;
+
+    self.serviced_count[interrupt.bit() as usize] += 1;
+    self.last_serviced = Some(interrupt);
+
+    Some(interrupt)
+  }
+
+  pub fn serviced_count(&self, interrupt: Interrupt) -> u64 {
+    self.serviced_count[interrupt.bit() as usize]
+  }
+
+  pub fn last_serviced(&self) -> Option<Interrupt> {
+    self.last_serviced
+  }
+
+  pub fn debug_print(&self) {
+    println!("-----------------");
+    println!(
+      "[IME: {:?}{}]",
+      self.ime,
+      if self.ime_scheduled {
+        " (scheduled)"
+      } else {
+        ""
+      }
+    );
+    println!("[IE: 0b{:>05b} IF: 0b{:>05b}]", self.ie, self.iflag);
+    println!("[Pending: {:?}]", self.next_pending());
+    println!("[Last serviced: {:?}]", self.last_serviced);
+    for interrupt in Interrupt::ALL.iter() {
+      println!("[{:?}: {}]", interrupt, self.serviced_count(*interrupt));
+    }
+    println!("-----------------");
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_service_priority() {
+    let mut ic = InterruptController::default();
+    ic.write_word(0xffff, 0b1_1111).unwrap();
+    ic.request(Interrupt::Joypad);
+    ic.request(Interrupt::Timer);
+    ic.enable();
+
+    assert_eq!(Some(Interrupt::Timer), ic.service());
+    assert_eq!(0b1111_0000, ic.read_word(0xff0f).unwrap());
+    assert!(!ic.is_master_enabled());
+
+    ic.enable();
+    assert_eq!(Some(Interrupt::Joypad), ic.service());
+    assert_eq!(0b1110_0000, ic.read_word(0xff0f).unwrap());
+    assert_eq!(1, ic.serviced_count(Interrupt::Timer));
+  }
+
+  #[test]
+  fn test_service_requires_enable_bit() {
+    let mut ic = InterruptController::default();
+    ic.write_word(0xffff, 0b0_0001).unwrap();
+    ic.request(Interrupt::Serial);
+    ic.enable();
+
+    assert_eq!(None, ic.service());
+    assert!(!ic.has_pending());
+  }
+
+  #[test]
+  fn test_ei_delay() {
+    let mut ic = InterruptController::default();
+    ic.write_word(0xffff, 0b0_0001).unwrap();
+    ic.request(Interrupt::VBlank);
+
+    ic.enable_delayed();
+    assert_eq!(None, ic.service());
+
+    ic.apply_delayed_enable();
+    assert_eq!(Some(Interrupt::VBlank), ic.service());
+  }
+
+  #[test]
+  fn test_di_cancels_ei() {
+    let mut ic = InterruptController::default();
+    ic.enable_delayed();
+    ic.disable();
+    ic.apply_delayed_enable();
+
+    assert!(!ic.is_master_enabled());
+  }
+}
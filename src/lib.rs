@@ -0,0 +1,40 @@
+#[macro_use]
+extern crate log;
+#[cfg(feature = "sdl")]
+extern crate sdl2;
+
+#[macro_use]
+pub mod macros;
+
+pub mod battery;
+pub mod blip_buffer;
+pub mod cartridge;
+pub mod channel;
+pub mod config;
+pub mod cpu;
+#[cfg(feature = "sdl")]
+pub mod debugger;
+pub mod display_adapter;
+pub mod emu;
+pub mod graphics;
+pub mod input;
+pub mod interrupt;
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+pub mod mem;
+pub mod noise_channel;
+pub mod palette;
+pub mod ring_buffer;
+#[cfg(feature = "sdl")]
+pub mod sdl_display;
+#[cfg(feature = "sdl")]
+pub mod sdl_frontend;
+pub mod serial;
+pub mod sound;
+pub mod sprite;
+pub mod square_channel;
+pub mod timer;
+pub mod util;
+pub mod wave_channel;
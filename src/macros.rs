@@ -170,6 +170,15 @@ macro_rules! op_bit_test {
   }};
 }
 
+macro_rules! op_bit_test_hl {
+  ($sel:ident, $bit:expr) => {{
+    let w = $sel.read_word($sel.cpu.reg_hl(), false);
+    $sel.cpu.set_flag_zero((bitn!(w, $bit) == 0b0).as_bit());
+    $sel.cpu.reset_flag_add_sub();
+    $sel.cpu.set_flag_half_carry(0b1);
+  }};
+}
+
 macro_rules! op_dec_reg {
   ($sel:ident, $reg:ident) => {{
     $sel
@@ -204,6 +213,57 @@ macro_rules! rot_left_reg {
   }};
 }
 
+macro_rules! rlc {
+  ($sel:ident, $reg:ident) => {{
+    let bit7 = bitn!($sel.cpu.$reg, 7);
+    $sel.cpu.set_flag_carry(bit7);
+
+    $sel.cpu.$reg = ($sel.cpu.$reg << 1) | bit7;
+    $sel.cpu.set_flag_zero_for($sel.cpu.$reg);
+
+    $sel.cpu.reset_flag_add_sub();
+    $sel.cpu.reset_flag_half_carry();
+  }};
+}
+
+macro_rules! rrc {
+  ($sel:ident, $reg:ident) => {{
+    let bit0 = bitn!($sel.cpu.$reg, 0);
+    $sel.cpu.set_flag_carry(bit0);
+
+    $sel.cpu.$reg = ($sel.cpu.$reg >> 1) | (bit0 << 7);
+    $sel.cpu.set_flag_zero_for($sel.cpu.$reg);
+
+    $sel.cpu.reset_flag_add_sub();
+    $sel.cpu.reset_flag_half_carry();
+  }};
+}
+
+macro_rules! sla {
+  ($sel:ident, $reg:ident) => {{
+    $sel.cpu.set_flag_carry(bitn!($sel.cpu.$reg, 7));
+
+    $sel.cpu.$reg = $sel.cpu.$reg << 1;
+    $sel.cpu.set_flag_zero_for($sel.cpu.$reg);
+
+    $sel.cpu.reset_flag_add_sub();
+    $sel.cpu.reset_flag_half_carry();
+  }};
+}
+
+macro_rules! sra {
+  ($sel:ident, $reg:ident) => {{
+    $sel.cpu.set_flag_carry(bitn!($sel.cpu.$reg, 0));
+
+    // Arithmetic shift keeps the sign bit.
+    $sel.cpu.$reg = ($sel.cpu.$reg >> 1) | ($sel.cpu.$reg & 0x80);
+    $sel.cpu.set_flag_zero_for($sel.cpu.$reg);
+
+    $sel.cpu.reset_flag_add_sub();
+    $sel.cpu.reset_flag_half_carry();
+  }};
+}
+
 macro_rules! op_sub_reg_from_a {
   ($sel:ident, $reg:expr) => {{
     $sel
@@ -222,17 +282,17 @@ macro_rules! op_sub_reg_from_a {
 
 macro_rules! adc_a {
   ($sel:ident, $reg:expr) => {{
+    let w = $reg;
     let old_carry = $sel.cpu.flag_carry().as_bit();
-    let acc = $reg.wrapping_add(old_carry);
 
-    $sel
-      .cpu
-      .set_flag_carry(Util::has_carry($sel.cpu.reg_a, acc).as_bit());
-    $sel
-      .cpu
-      .set_flag_half_carry(Util::has_half_carry($sel.cpu.reg_a, acc).as_bit());
+    $sel.cpu.set_flag_carry(
+      Util::has_carry_with_carry($sel.cpu.reg_a, w, old_carry).as_bit(),
+    );
+    $sel.cpu.set_flag_half_carry(
+      Util::has_half_carry_with_carry($sel.cpu.reg_a, w, old_carry).as_bit(),
+    );
 
-    $sel.cpu.reg_a = $sel.cpu.reg_a.wrapping_add($reg);
+    $sel.cpu.reg_a = $sel.cpu.reg_a.wrapping_add(w);
     $sel.cpu.reg_a = $sel.cpu.reg_a.wrapping_add(old_carry);
 
     $sel.cpu.set_flag_zero_for($sel.cpu.reg_a);
@@ -240,6 +300,44 @@ macro_rules! adc_a {
   }};
 }
 
+macro_rules! sbc_a {
+  ($sel:ident, $reg:expr) => {{
+    let w = $reg;
+    let old_carry = $sel.cpu.flag_carry().as_bit();
+
+    $sel.cpu.set_flag_carry(
+      Util::has_borrow_with_carry($sel.cpu.reg_a, w, old_carry).as_bit(),
+    );
+    $sel.cpu.set_flag_half_carry(
+      Util::has_half_borrow_with_carry($sel.cpu.reg_a, w, old_carry).as_bit(),
+    );
+
+    $sel.cpu.reg_a = $sel.cpu.reg_a.wrapping_sub(w);
+    $sel.cpu.reg_a = $sel.cpu.reg_a.wrapping_sub(old_carry);
+
+    $sel.cpu.set_flag_zero_for($sel.cpu.reg_a);
+    $sel.cpu.set_flag_add_sub(0x1);
+  }};
+}
+
+// Flags of ADD SP,r8 and LD HL,SP+r8 are calculated on the low byte as an unsigned addition.
+macro_rules! sp_add_signed_word {
+  ($sel:ident, $w:expr) => {{
+    let w: u8 = $w;
+
+    $sel.cpu.reset_flag_zero();
+    $sel.cpu.reset_flag_add_sub();
+    $sel
+      .cpu
+      .set_flag_half_carry(Util::has_half_carry($sel.cpu.sp.lo(), w).as_bit());
+    $sel
+      .cpu
+      .set_flag_carry(Util::has_carry($sel.cpu.sp.lo(), w).as_bit());
+
+    Util::dword_signed_add($sel.cpu.sp, w as i8)
+  }};
+}
+
 macro_rules! srl {
   ($sel:ident, $reg:ident) => {{
     $sel.cpu.set_flag_carry(bitn!($sel.cpu.$reg, 0));
@@ -1,25 +1,16 @@
 #[macro_use]
 extern crate log;
 extern crate env_logger;
-extern crate sdl2;
+extern crate yagbe;
 
-#[macro_use]
-pub mod macros;
-
-pub mod cpu;
-pub mod debugger;
-pub mod display_adapter;
-pub mod emu;
-pub mod graphics;
-pub mod input;
-pub mod mem;
-pub mod serial;
-pub mod sound;
-pub mod timer;
-pub mod util;
-
-use self::emu::*;
 use std::env;
+use std::fmt;
+use std::path::Path;
+use std::process;
+use yagbe::config::*;
+use yagbe::emu::*;
+#[cfg(feature = "sdl")]
+use yagbe::sdl_frontend::*;
 
 fn main() {
   env_logger::init();
@@ -28,18 +19,126 @@ fn main() {
 
   let args: Vec<String> = env::args().collect();
   if args.len() < 2 {
-    panic!("Missing argument. Call: cargo run -- CARTRIGE [--debug]");
+    exit_with_error("Missing argument. Call: cargo run -- CARTRIGE [--debug]");
   }
 
-  let mut emu = Emu::new(args[1].clone());
+  let config_path = match args.iter().position(|arg| arg == "--config") {
+    Some(pos) => Some(args.get(pos + 1).unwrap_or_else(|| {
+      exit_with_error("Missing config path. Call: cargo run -- CARTRIGE --config CONFIG")
+    })),
+    None if Path::new(DEFAULT_CONFIG_FILE).exists() => Some(&DEFAULT_CONFIG_FILE.to_string()),
+    None => None,
+  };
+  let config = match config_path {
+    Some(path) => Config::load(path)
+      .unwrap_or_else(|err| exit_with_error(format!("Cannot load config: {}", err))),
+    None => Config::default(),
+  };
 
-  if args.iter().find(|&arg| arg == "--debug").is_some() {
-    emu.enable_debug_mode();
+  let is_headless = args.iter().find(|&arg| arg == "--headless").is_some();
+
+  let mut emu = Emu::new(args[1].clone()).unwrap_or_else(|err| exit_with_error(err));
+
+  if let Some(pos) = args.iter().position(|arg| arg == "--boot-rom") {
+    let path = args.get(pos + 1).unwrap_or_else(|| {
+      exit_with_error("Missing boot ROM path. Call: cargo run -- CARTRIGE --boot-rom BOOT_ROM")
+    });
+    emu
+      .load_boot_rom(path)
+      .unwrap_or_else(|err| exit_with_error(format!("Cannot load boot ROM: {}", err)));
   }
 
   if args.iter().find(|&arg| arg == "--no-sound").is_some() {
     emu.mute_sound();
   }
 
-  emu.run();
+  if is_headless {
+    if args.iter().find(|&arg| arg == "--debug").is_some() {
+      warn!("The debugger is not available in headless mode.");
+    }
+
+    let limit = parse_headless_limit(&args).unwrap_or_else(|err| exit_with_error(err));
+    if let Err(err) = run_headless(&mut emu, limit) {
+      exit_with_error(err);
+    }
+    return;
+  }
+
+  run_frontend(&mut emu, config, &args);
+}
+
+#[cfg(feature = "sdl")]
+fn run_frontend(emu: &mut Emu, config: Config, args: &[String]) {
+  let pacing = if args.iter().find(|&arg| arg == "--unthrottled").is_some() {
+    Pacing::Unthrottled
+  } else if args.iter().find(|&arg| arg == "--vsync").is_some() {
+    Pacing::Vsync
+  } else {
+    Pacing::Audio
+  };
+
+  let mut frontend = SdlFrontend::with_pacing(pacing).unwrap_or_else(|err| exit_with_error(err));
+  frontend.set_output_palette(config.palette);
+  frontend.set_key_bindings(&config.key_bindings);
+  frontend.set_pad_config(config.pad_config);
+
+  if args.iter().find(|&arg| arg == "--debug").is_some() {
+    frontend
+      .enable_debug_mode(emu)
+      .unwrap_or_else(|err| exit_with_error(err));
+  }
+
+  if let Err(err) = frontend.run(emu) {
+    exit_with_error(err);
+  }
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_frontend(_emu: &mut Emu, _config: Config, _args: &[String]) {
+  eprintln!("Built without the sdl feature, only --headless is available.");
+  process::exit(1);
+}
+
+enum HeadlessLimit {
+  Frames(u64),
+  Cycles(u64),
+}
+
+fn parse_headless_limit(args: &[String]) -> Result<HeadlessLimit, String> {
+  let limit_arg = |name: &str| match args.iter().position(|arg| arg == name) {
+    Some(pos) => match args.get(pos + 1).map(|value| value.parse::<u64>()) {
+      Some(Ok(value)) => Ok(Some(value)),
+      _ => Err(format!(
+        "Invalid {} value. Call: cargo run -- CARTRIGE --headless {} N",
+        name, name
+      )),
+    },
+    None => Ok(None),
+  };
+
+  match (limit_arg("--frames")?, limit_arg("--cycles")?) {
+    (Some(frames), _) => Ok(HeadlessLimit::Frames(frames)),
+    (None, Some(cycles)) => Ok(HeadlessLimit::Cycles(cycles)),
+    (None, None) => Err(
+      "Missing limit. Call: cargo run -- CARTRIGE --headless (--frames N | --cycles N)".to_string(),
+    ),
+  }
+}
+
+// Runs for the given number of frames or cycles, then saves the battery and prints the serial output.
+fn run_headless(emu: &mut Emu, limit: HeadlessLimit) -> Result<(), EmuError> {
+  let result = match limit {
+    HeadlessLimit::Frames(frames) => emu.run_frames(frames),
+    HeadlessLimit::Cycles(cycles) => emu.run_cycles(cycles),
+  };
+  emu.flush_battery_save();
+
+  print!("{}", String::from_utf8_lossy(emu.serial_output()));
+  result
+}
+
+// Bad arguments, input files and emulation errors end the program with the message.
+fn exit_with_error(err: impl fmt::Display) -> ! {
+  eprintln!("{}", err);
+  process::exit(1);
 }
@@ -0,0 +1,231 @@
+use super::cartridge::*;
+
+const LOGO_START: usize = 0x0104;
+const LOGO_END: usize = 0x0134;
+
+pub struct Mbc1 {
+  rom: Vec<u8>,
+  ram: Vec<u8>,
+  ram_enabled: bool,
+  // 5 bit register, lower bits of the ROM bank number.
+  bank1: u8,
+  // 2 bit register, upper bits of the ROM bank number or the RAM bank number.
+  bank2: u8,
+  advanced_banking_mode: bool,
+  // MBC1M multicarts wire only 4 bits of BANK1 to the ROM.
+  is_multicart: bool,
+}
+
+impl Mbc1 {
+  pub fn new(rom: Vec<u8>, ram_size: usize) -> Mbc1 {
+    let is_multicart = Mbc1::is_multicart(&rom);
+    if is_multicart {
+      info!("MBC1M multicart detected.");
+    }
+
+    Mbc1 {
+      rom,
+      ram: vec![0; ram_size],
+      ram_enabled: false,
+      bank1: 1,
+      bank2: 0,
+      advanced_banking_mode: false,
+      is_multicart,
+    }
+  }
+
+  // Multicarts are 8 Mbit images where the game in the 0x10th bank has its own boot logo.
+  fn is_multicart(rom: &[u8]) -> bool {
+    let second_game_offs = 0x10 * ROM_BANK_SIZE;
+
+    rom.len() == 64 * ROM_BANK_SIZE
+      && rom[LOGO_START..LOGO_END]
+        == rom[(second_game_offs + LOGO_START)..(second_game_offs + LOGO_END)]
+  }
+
+  fn bank2_shift(&self) -> u8 {
+    if self.is_multicart {
+      4
+    } else {
+      5
+    }
+  }
+
+  fn bank1_mask(&self) -> u8 {
+    if self.is_multicart {
+      0b1111
+    } else {
+      0b1_1111
+    }
+  }
+
+  fn rom_bank_zero_area(&self) -> usize {
+    if self.advanced_banking_mode {
+      (self.bank2 << self.bank2_shift()) as usize
+    } else {
+      0
+    }
+  }
+
+  fn rom_bank_switchable_area(&self) -> usize {
+    ((self.bank2 << self.bank2_shift()) | (self.bank1 & self.bank1_mask())) as usize
+  }
+
+  fn ram_bank(&self) -> usize {
+    if self.advanced_banking_mode {
+      self.bank2 as usize
+    } else {
+      0
+    }
+  }
+}
+
+impl MemoryBankController for Mbc1 {
+  fn read_rom(&self, addr: u16) -> u8 {
+    match addr {
+      0x0000...0x3fff => read_banked(&self.rom, ROM_BANK_SIZE, self.rom_bank_zero_area(), addr),
+      _ => read_banked(
+        &self.rom,
+        ROM_BANK_SIZE,
+        self.rom_bank_switchable_area(),
+        addr,
+      ),
+    }
+  }
+
+  fn write_rom(&mut self, addr: u16, w: u8) {
+    match addr {
+      0x0000...0x1fff => self.ram_enabled = w & 0b1111 == 0xa,
+      0x2000...0x3fff => {
+        // Bank 0 cannot be selected, though the check is done on all 5 bits - so 0x20, 0x40, 0x60 are not
+        // reachable in the switchable area (in mode 0).
+        self.bank1 = match w & 0b1_1111 {
+          0 => 1,
+          bank => bank,
+        };
+      }
+      0x4000...0x5fff => self.bank2 = w & 0b11,
+      0x6000...0x7fff => self.advanced_banking_mode = w & 0b1 == 0b1,
+      _ => unreachable!(),
+    }
+  }
+
+  fn read_ram(&self, addr: u16) -> u8 {
+    if !self.ram_enabled {
+      return 0xff;
+    }
+
+    read_banked(&self.ram, RAM_BANK_SIZE, self.ram_bank(), addr)
+  }
+
+  fn write_ram(&mut self, addr: u16, w: u8) {
+    if !self.ram_enabled {
+      return;
+    }
+
+    let bank = self.ram_bank();
+    write_banked(&mut self.ram, RAM_BANK_SIZE, bank, addr, w);
+  }
+
+  fn battery_data(&self) -> Vec<u8> {
+    self.ram.clone()
+  }
+
+  fn load_battery_data(&mut self, data: &[u8]) {
+    load_ram(&mut self.ram, data);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  // Every bank starts with its own bank number.
+  fn make_rom(banks: usize) -> Vec<u8> {
+    let mut rom = vec![0; banks * ROM_BANK_SIZE];
+    for bank in 0..banks {
+      rom[bank * ROM_BANK_SIZE] = bank as u8;
+    }
+    rom
+  }
+
+  #[test]
+  fn test_rom_banking() {
+    let mut mbc = Mbc1::new(make_rom(128), 0);
+
+    assert_eq!(1, mbc.read_rom(0x4000));
+
+    mbc.write_rom(0x2000, 0x00);
+    assert_eq!(1, mbc.read_rom(0x4000));
+
+    mbc.write_rom(0x2000, 0x1f);
+    assert_eq!(0x1f, mbc.read_rom(0x4000));
+
+    mbc.write_rom(0x4000, 0x02);
+    assert_eq!(0x5f, mbc.read_rom(0x4000));
+
+    // 0x40 is mapped as 0x41.
+    mbc.write_rom(0x2000, 0x00);
+    assert_eq!(0x41, mbc.read_rom(0x4000));
+    assert_eq!(0x00, mbc.read_rom(0x0000));
+  }
+
+  #[test]
+  fn test_bank_zero_area_in_advanced_mode() {
+    let mut mbc = Mbc1::new(make_rom(128), 0);
+    mbc.write_rom(0x4000, 0x01);
+    assert_eq!(0x00, mbc.read_rom(0x0000));
+
+    mbc.write_rom(0x6000, 0x01);
+    assert_eq!(0x20, mbc.read_rom(0x0000));
+  }
+
+  #[test]
+  fn test_rom_bank_wraps_around_rom_size() {
+    let mut mbc = Mbc1::new(make_rom(4), 0);
+    mbc.write_rom(0x2000, 0x05);
+    assert_eq!(0x01, mbc.read_rom(0x4000));
+  }
+
+  #[test]
+  fn test_ram_banking() {
+    let mut mbc = Mbc1::new(make_rom(4), 4 * RAM_BANK_SIZE);
+
+    mbc.write_ram(0xa000, 0x12);
+    assert_eq!(0xff, mbc.read_ram(0xa000));
+
+    mbc.write_rom(0x0000, 0x0a);
+    mbc.write_ram(0xa000, 0x12);
+    assert_eq!(0x12, mbc.read_ram(0xa000));
+
+    // Mode 0 always uses the first RAM bank.
+    mbc.write_rom(0x4000, 0x02);
+    assert_eq!(0x12, mbc.read_ram(0xa000));
+
+    mbc.write_rom(0x6000, 0x01);
+    assert_eq!(0x00, mbc.read_ram(0xa000));
+    mbc.write_ram(0xa000, 0x34);
+    assert_eq!(0x34, mbc.read_ram(0xa000));
+
+    mbc.write_rom(0x0000, 0x00);
+    assert_eq!(0xff, mbc.read_ram(0xa000));
+  }
+
+  #[test]
+  fn test_multicart() {
+    let mut rom = make_rom(64);
+    for i in LOGO_START..LOGO_END {
+      rom[i] = i as u8;
+      rom[0x10 * ROM_BANK_SIZE + i] = i as u8;
+    }
+    let mut mbc = Mbc1::new(rom, 0);
+    assert!(mbc.is_multicart);
+
+    mbc.write_rom(0x4000, 0x01);
+    mbc.write_rom(0x2000, 0x12);
+    assert_eq!(0x12, mbc.read_rom(0x4000));
+
+    mbc.write_rom(0x6000, 0x01);
+    assert_eq!(0x10, mbc.read_rom(0x0000));
+  }
+}
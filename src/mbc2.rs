@@ -0,0 +1,111 @@
+use super::cartridge::*;
+
+// 512 x 4 bit RAM built into the controller.
+const RAM_SIZE: usize = 0x200;
+
+pub struct Mbc2 {
+  rom: Vec<u8>,
+  ram: Vec<u8>,
+  ram_enabled: bool,
+  // 4 bit register.
+  rom_bank: u8,
+}
+
+impl Mbc2 {
+  pub fn new(rom: Vec<u8>) -> Mbc2 {
+    Mbc2 {
+      rom,
+      ram: vec![0; RAM_SIZE],
+      ram_enabled: false,
+      rom_bank: 1,
+    }
+  }
+}
+
+impl MemoryBankController for Mbc2 {
+  fn read_rom(&self, addr: u16) -> u8 {
+    match addr {
+      0x0000...0x3fff => read_banked(&self.rom, ROM_BANK_SIZE, 0, addr),
+      _ => read_banked(&self.rom, ROM_BANK_SIZE, self.rom_bank as usize, addr),
+    }
+  }
+
+  fn write_rom(&mut self, addr: u16, w: u8) {
+    // Bit 8 of the address selects between the RAM enable and the ROM bank register.
+    if addr <= 0x3fff {
+      if bitn!(addr, 8) == 0x0 {
+        self.ram_enabled = w & 0b1111 == 0xa;
+      } else {
+        self.rom_bank = match w & 0b1111 {
+          0 => 1,
+          bank => bank,
+        };
+      }
+    }
+  }
+
+  fn read_ram(&self, addr: u16) -> u8 {
+    if !self.ram_enabled {
+      return 0xff;
+    }
+
+    // Only the lower nibble is connected, the RAM is echoed through the whole 0xa000 - 0xbfff area.
+    self.ram[addr as usize & (RAM_SIZE - 1)] | 0xf0
+  }
+
+  fn write_ram(&mut self, addr: u16, w: u8) {
+    if !self.ram_enabled {
+      return;
+    }
+
+    self.ram[addr as usize & (RAM_SIZE - 1)] = w & 0x0f;
+  }
+
+  fn battery_data(&self) -> Vec<u8> {
+    self.ram.clone()
+  }
+
+  fn load_battery_data(&mut self, data: &[u8]) {
+    load_ram(&mut self.ram, data);
+    for b in self.ram.iter_mut() {
+      *b &= 0x0f;
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_register_select() {
+    let mut rom = vec![0; 16 * ROM_BANK_SIZE];
+    rom[0xf * ROM_BANK_SIZE] = 0x0f;
+    rom[ROM_BANK_SIZE] = 0x01;
+    let mut mbc = Mbc2::new(rom);
+
+    // Bit 8 is clear, RAM enable register.
+    mbc.write_rom(0x2000, 0x0f);
+    assert_eq!(0x01, mbc.read_rom(0x4000));
+
+    mbc.write_rom(0x2100, 0x0f);
+    assert_eq!(0x0f, mbc.read_rom(0x4000));
+
+    mbc.write_rom(0x0100, 0x00);
+    assert_eq!(0x01, mbc.read_rom(0x4000));
+  }
+
+  #[test]
+  fn test_half_byte_ram() {
+    let mut mbc = Mbc2::new(vec![0; 2 * ROM_BANK_SIZE]);
+
+    mbc.write_ram(0xa000, 0x0f);
+    assert_eq!(0xff, mbc.read_ram(0xa000));
+
+    mbc.write_rom(0x0000, 0x0a);
+    mbc.write_ram(0xa000, 0x12);
+    assert_eq!(0xf2, mbc.read_ram(0xa000));
+    assert_eq!(0xf2, mbc.read_ram(0xa200));
+    assert_eq!(0xf2, mbc.read_ram(0xbe00));
+  }
+}
@@ -0,0 +1,351 @@
+use super::cartridge::*;
+use super::util::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Current and latched registers as 4 byte values, followed by a 64 bit timestamp.
+const RTC_SAVE_SIZE: usize = 48;
+// Older variant with a 32 bit timestamp.
+const RTC_SAVE_SIZE_SHORT: usize = 44;
+
+#[derive(Debug, Default, Clone)]
+pub struct Rtc {
+  seconds: u8,
+  minutes: u8,
+  hours: u8,
+  // 9 bit day counter.
+  days: u16,
+  halted: bool,
+  day_carry: bool,
+  latched: [u8; 5],
+  latch_armed: bool,
+  // Unix timestamp of the last synchronization with the host clock.
+  last_sync: u64,
+}
+
+impl Rtc {
+  pub fn new(now: u64) -> Rtc {
+    Rtc {
+      last_sync: now,
+      ..Default::default()
+    }
+  }
+
+  pub fn now() -> u64 {
+    SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs())
+      .unwrap_or(0)
+  }
+
+  // Advances the clock with the host time elapsed since the last sync.
+  pub fn sync(&mut self, now: u64) {
+    if now > self.last_sync && !self.halted {
+      self.advance(now - self.last_sync);
+    }
+    self.last_sync = now;
+  }
+
+  fn advance(&mut self, secs: u64) {
+    let total = secs
+      + self.seconds as u64
+      + self.minutes as u64 * 60
+      + self.hours as u64 * 3600
+      + self.days as u64 * 86400;
+
+    self.seconds = (total % 60) as u8;
+    self.minutes = ((total / 60) % 60) as u8;
+    self.hours = ((total / 3600) % 24) as u8;
+
+    let days = total / 86400;
+    if days > 0x1ff {
+      self.day_carry = true;
+    }
+    self.days = (days & 0x1ff) as u16;
+  }
+
+  fn registers(&self) -> [u8; 5] {
+    [
+      self.seconds,
+      self.minutes,
+      self.hours,
+      self.days as u8,
+      ((self.days >> 8) as u8 & 0b1) | (self.halted.as_bit() << 6) | (self.day_carry.as_bit() << 7),
+    ]
+  }
+
+  // Writing 0x00 and then 0x01 copies the current time into the readable registers.
+  pub fn write_latch(&mut self, w: u8, now: u64) {
+    if self.latch_armed && w == 0x01 {
+      self.sync(now);
+      self.latched = self.registers();
+    }
+    self.latch_armed = w == 0x00;
+  }
+
+  pub fn read_register(&self, reg: u8) -> u8 {
+    match reg {
+      0x08...0x0c => self.latched[(reg - 0x08) as usize],
+      _ => 0xff,
+    }
+  }
+
+  pub fn write_register(&mut self, reg: u8, w: u8, now: u64) {
+    self.sync(now);
+
+    match reg {
+      0x08 => self.seconds = w & 0b11_1111,
+      0x09 => self.minutes = w & 0b11_1111,
+      0x0a => self.hours = w & 0b1_1111,
+      0x0b => self.days = (self.days & 0x100) | w as u16,
+      0x0c => {
+        self.days = (self.days & 0xff) | ((w as u16 & 0b1) << 8);
+        self.halted = bitn!(w, 6) == 0x1;
+        self.day_carry = bitn!(w, 7) == 0x1;
+      }
+      _ => {}
+    }
+
+    // The written value is visible without latching again.
+    self.latched = self.registers();
+  }
+
+  pub fn to_bytes(&self, now: u64) -> Vec<u8> {
+    let mut synced = self.clone();
+    synced.sync(now);
+
+    let mut out = Vec::with_capacity(RTC_SAVE_SIZE);
+    for reg in synced.registers().iter().chain(synced.latched.iter()) {
+      out.extend_from_slice(&(*reg as u32).to_le_bytes());
+    }
+    out.extend_from_slice(&synced.last_sync.to_le_bytes());
+    out
+  }
+
+  pub fn from_bytes(data: &[u8], now: u64) -> Option<Rtc> {
+    if data.len() != RTC_SAVE_SIZE && data.len() != RTC_SAVE_SIZE_SHORT {
+      return None;
+    }
+
+    let reg = |i: usize| data[i * 4];
+    let mut rtc = Rtc::default();
+    rtc.write_register(0x08, reg(0), 0);
+    rtc.write_register(0x09, reg(1), 0);
+    rtc.write_register(0x0a, reg(2), 0);
+    rtc.write_register(0x0b, reg(3), 0);
+    rtc.write_register(0x0c, reg(4), 0);
+    for i in 0..5 {
+      rtc.latched[i] = reg(5 + i);
+    }
+
+    let mut timestamp = [0u8; 8];
+    timestamp[..(data.len() - 40)].copy_from_slice(&data[40..]);
+    rtc.last_sync = u64::from_le_bytes(timestamp);
+
+    // Catch up with the time passed while the emulator was off.
+    rtc.sync(now);
+    Some(rtc)
+  }
+}
+
+pub struct Mbc3 {
+  rom: Vec<u8>,
+  ram: Vec<u8>,
+  rtc: Option<Rtc>,
+  ram_and_timer_enabled: bool,
+  rom_bank: u8,
+  // 0x00-0x03: RAM bank, 0x08-0x0c: RTC register.
+  ram_bank_or_rtc_register: u8,
+}
+
+impl Mbc3 {
+  pub fn new(rom: Vec<u8>, ram_size: usize, has_timer: bool) -> Mbc3 {
+    Mbc3 {
+      rom,
+      ram: vec![0; ram_size],
+      rtc: if has_timer {
+        Some(Rtc::new(Rtc::now()))
+      } else {
+        None
+      },
+      ram_and_timer_enabled: false,
+      rom_bank: 1,
+      ram_bank_or_rtc_register: 0,
+    }
+  }
+
+  fn write_rom_at(&mut self, addr: u16, w: u8, now: u64) {
+    match addr {
+      0x0000...0x1fff => self.ram_and_timer_enabled = w & 0b1111 == 0xa,
+      0x2000...0x3fff => {
+        self.rom_bank = match w & 0b111_1111 {
+          0 => 1,
+          bank => bank,
+        };
+      }
+      0x4000...0x5fff => self.ram_bank_or_rtc_register = w & 0b1111,
+      0x6000...0x7fff => {
+        if let Some(rtc) = self.rtc.as_mut() {
+          rtc.write_latch(w, now);
+        }
+      }
+      _ => unreachable!(),
+    }
+  }
+
+  fn write_ram_at(&mut self, addr: u16, w: u8, now: u64) {
+    if !self.ram_and_timer_enabled {
+      return;
+    }
+
+    match self.ram_bank_or_rtc_register {
+      bank @ 0x00...0x03 => write_banked(&mut self.ram, RAM_BANK_SIZE, bank as usize, addr, w),
+      reg @ 0x08...0x0c => {
+        if let Some(rtc) = self.rtc.as_mut() {
+          rtc.write_register(reg, w, now);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  fn battery_data_at(&self, now: u64) -> Vec<u8> {
+    let mut data = self.ram.clone();
+    if let Some(rtc) = self.rtc.as_ref() {
+      data.extend(rtc.to_bytes(now));
+    }
+    data
+  }
+
+  fn load_battery_data_at(&mut self, data: &[u8], now: u64) {
+    load_ram(&mut self.ram, data);
+    let ram_len = self.ram.len().min(data.len());
+
+    if self.rtc.is_some() {
+      match Rtc::from_bytes(&data[ram_len..], now) {
+        Some(rtc) => self.rtc = Some(rtc),
+        None => warn!("Save file has no valid RTC data, clock is reset."),
+      }
+    }
+  }
+}
+
+impl MemoryBankController for Mbc3 {
+  fn read_rom(&self, addr: u16) -> u8 {
+    match addr {
+      0x0000...0x3fff => read_banked(&self.rom, ROM_BANK_SIZE, 0, addr),
+      _ => read_banked(&self.rom, ROM_BANK_SIZE, self.rom_bank as usize, addr),
+    }
+  }
+
+  fn write_rom(&mut self, addr: u16, w: u8) {
+    self.write_rom_at(addr, w, Rtc::now());
+  }
+
+  fn read_ram(&self, addr: u16) -> u8 {
+    if !self.ram_and_timer_enabled {
+      return 0xff;
+    }
+
+    match self.ram_bank_or_rtc_register {
+      bank @ 0x00...0x03 => read_banked(&self.ram, RAM_BANK_SIZE, bank as usize, addr),
+      reg @ 0x08...0x0c => self.rtc.as_ref().map_or(0xff, |rtc| rtc.read_register(reg)),
+      _ => 0xff,
+    }
+  }
+
+  fn write_ram(&mut self, addr: u16, w: u8) {
+    self.write_ram_at(addr, w, Rtc::now());
+  }
+
+  fn battery_data(&self) -> Vec<u8> {
+    self.battery_data_at(Rtc::now())
+  }
+
+  fn load_battery_data(&mut self, data: &[u8]) {
+    self.load_battery_data_at(data, Rtc::now());
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn read_rtc(mbc: &Mbc3, reg: u8) -> u8 {
+    mbc.rtc.as_ref().unwrap().read_register(reg)
+  }
+
+  #[test]
+  fn test_rom_banking() {
+    let mut rom = vec![0; 128 * ROM_BANK_SIZE];
+    rom[0x7f * ROM_BANK_SIZE] = 0x7f;
+    rom[ROM_BANK_SIZE] = 0x01;
+    let mut mbc = Mbc3::new(rom, 0, false);
+
+    mbc.write_rom(0x2000, 0x7f);
+    assert_eq!(0x7f, mbc.read_rom(0x4000));
+
+    mbc.write_rom(0x2000, 0x00);
+    assert_eq!(0x01, mbc.read_rom(0x4000));
+  }
+
+  #[test]
+  fn test_rtc_latch() {
+    let mut mbc = Mbc3::new(vec![0; 2 * ROM_BANK_SIZE], 0, true);
+    mbc.rtc = Some(Rtc::new(1000));
+    mbc.write_rom_at(0x0000, 0x0a, 1000);
+    mbc.write_rom_at(0x4000, 0x08, 1000);
+
+    // 1 day, 1 hour, 1 minute and 1 second later.
+    mbc.write_rom_at(0x6000, 0x00, 1000 + 90061);
+    assert_eq!(0, read_rtc(&mbc, 0x08));
+    mbc.write_rom_at(0x6000, 0x01, 1000 + 90061);
+
+    assert_eq!(1, read_rtc(&mbc, 0x08));
+    assert_eq!(1, read_rtc(&mbc, 0x09));
+    assert_eq!(1, read_rtc(&mbc, 0x0a));
+    assert_eq!(1, read_rtc(&mbc, 0x0b));
+    assert_eq!(0, read_rtc(&mbc, 0x0c));
+    assert_eq!(1, mbc.read_ram(0xa000));
+  }
+
+  #[test]
+  fn test_rtc_halt_and_day_carry() {
+    let mut rtc = Rtc::new(0);
+    rtc.write_register(0x0b, 0xff, 0);
+    rtc.write_register(0x0c, 0x01, 0);
+    rtc.sync(86400);
+    assert_eq!(0b1000_0000, rtc.registers()[4]);
+    assert_eq!(0, rtc.registers()[3]);
+
+    rtc.write_register(0x0c, 0b0100_0000, 86400);
+    rtc.sync(2 * 86400);
+    assert_eq!(0, rtc.registers()[3]);
+    assert_eq!(0, rtc.registers()[0]);
+  }
+
+  #[test]
+  fn test_save_format() {
+    let mut mbc = Mbc3::new(vec![0; 2 * ROM_BANK_SIZE], RAM_BANK_SIZE, true);
+    mbc.rtc = Some(Rtc::new(1000));
+    mbc.write_rom_at(0x0000, 0x0a, 1000);
+    mbc.write_ram_at(0xa000, 0x42, 1000);
+    mbc.write_rom_at(0x4000, 0x09, 1000);
+    mbc.write_ram_at(0xa000, 30, 1000);
+
+    let data = mbc.battery_data_at(1010);
+    assert_eq!(RAM_BANK_SIZE + RTC_SAVE_SIZE, data.len());
+    assert_eq!(0x42, data[0]);
+    assert_eq!(&[10, 0, 0, 0], &data[RAM_BANK_SIZE..RAM_BANK_SIZE + 4]);
+    assert_eq!(&[30, 0, 0, 0], &data[RAM_BANK_SIZE + 4..RAM_BANK_SIZE + 8]);
+    assert_eq!(1010u64.to_le_bytes(), data[RAM_BANK_SIZE + 40..]);
+
+    // The clock keeps going while the emulator is off.
+    let mut restored = Mbc3::new(vec![0; 2 * ROM_BANK_SIZE], RAM_BANK_SIZE, true);
+    restored.load_battery_data_at(&data, 1010 + 3600);
+    let rtc = restored.rtc.as_ref().unwrap();
+    assert_eq!(10, rtc.registers()[0]);
+    assert_eq!(30, rtc.registers()[1]);
+    assert_eq!(1, rtc.registers()[2]);
+    assert_eq!(0x42, restored.ram[0]);
+  }
+}
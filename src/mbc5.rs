@@ -0,0 +1,153 @@
+use super::cartridge::*;
+
+pub struct Mbc5 {
+  rom: Vec<u8>,
+  ram: Vec<u8>,
+  ram_enabled: bool,
+  // 9 bit register, bank 0 can be mapped to the switchable area as well.
+  rom_bank: u16,
+  ram_bank: u8,
+  // Rumble cartridges use bit 3 of the RAM bank register to turn on the motor.
+  has_rumble: bool,
+  is_rumbling: bool,
+  events: Vec<CartridgeEvent>,
+}
+
+impl Mbc5 {
+  pub fn new(rom: Vec<u8>, ram_size: usize, has_rumble: bool) -> Mbc5 {
+    Mbc5 {
+      rom,
+      ram: vec![0; ram_size],
+      ram_enabled: false,
+      rom_bank: 1,
+      ram_bank: 0,
+      has_rumble,
+      is_rumbling: false,
+      events: vec![],
+    }
+  }
+
+  fn set_rumble(&mut self, is_rumbling: bool) {
+    if self.is_rumbling != is_rumbling {
+      self.is_rumbling = is_rumbling;
+      self.events.push(CartridgeEvent::Rumble(is_rumbling));
+    }
+  }
+}
+
+impl MemoryBankController for Mbc5 {
+  fn read_rom(&self, addr: u16) -> u8 {
+    match addr {
+      0x0000...0x3fff => read_banked(&self.rom, ROM_BANK_SIZE, 0, addr),
+      _ => read_banked(&self.rom, ROM_BANK_SIZE, self.rom_bank as usize, addr),
+    }
+  }
+
+  fn write_rom(&mut self, addr: u16, w: u8) {
+    match addr {
+      // Unlike MBC1, all 8 bits are checked.
+      0x0000...0x1fff => self.ram_enabled = w == 0x0a,
+      0x2000...0x2fff => self.rom_bank = (self.rom_bank & 0x100) | w as u16,
+      0x3000...0x3fff => self.rom_bank = (self.rom_bank & 0xff) | ((w as u16 & 0b1) << 8),
+      0x4000...0x5fff => {
+        if self.has_rumble {
+          self.ram_bank = w & 0b111;
+          self.set_rumble(bitn!(w, 3) == 0x1);
+        } else {
+          self.ram_bank = w & 0b1111;
+        }
+      }
+      0x6000...0x7fff => {}
+      _ => unreachable!(),
+    }
+  }
+
+  fn read_ram(&self, addr: u16) -> u8 {
+    if !self.ram_enabled {
+      return 0xff;
+    }
+
+    read_banked(&self.ram, RAM_BANK_SIZE, self.ram_bank as usize, addr)
+  }
+
+  fn write_ram(&mut self, addr: u16, w: u8) {
+    if !self.ram_enabled {
+      return;
+    }
+
+    write_banked(
+      &mut self.ram,
+      RAM_BANK_SIZE,
+      self.ram_bank as usize,
+      addr,
+      w,
+    );
+  }
+
+  fn battery_data(&self) -> Vec<u8> {
+    self.ram.clone()
+  }
+
+  fn load_battery_data(&mut self, data: &[u8]) {
+    load_ram(&mut self.ram, data);
+  }
+
+  fn drain_events(&mut self) -> Vec<CartridgeEvent> {
+    self.events.drain(..).collect()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_rom_banking() {
+    let mut rom = vec![0; 512 * ROM_BANK_SIZE];
+    rom[0x1ff * ROM_BANK_SIZE] = 0xaa;
+    rom[0x100 * ROM_BANK_SIZE] = 0xbb;
+    let mut mbc = Mbc5::new(rom, 0, false);
+
+    mbc.write_rom(0x2000, 0xff);
+    mbc.write_rom(0x3000, 0x01);
+    assert_eq!(0xaa, mbc.read_rom(0x4000));
+
+    mbc.write_rom(0x2000, 0x00);
+    assert_eq!(0xbb, mbc.read_rom(0x4000));
+
+    // Bank 0 is not translated to bank 1.
+    mbc.write_rom(0x3000, 0x00);
+    assert_eq!(mbc.read_rom(0x0000), mbc.read_rom(0x4000));
+  }
+
+  #[test]
+  fn test_ram_banking() {
+    let mut mbc = Mbc5::new(vec![0; 2 * ROM_BANK_SIZE], 16 * RAM_BANK_SIZE, false);
+    mbc.write_rom(0x0000, 0x0a);
+
+    mbc.write_rom(0x4000, 0x0f);
+    mbc.write_ram(0xa000, 0x12);
+    mbc.write_rom(0x4000, 0x00);
+    assert_eq!(0x00, mbc.read_ram(0xa000));
+    mbc.write_rom(0x4000, 0x0f);
+    assert_eq!(0x12, mbc.read_ram(0xa000));
+  }
+
+  #[test]
+  fn test_rumble() {
+    let mut mbc = Mbc5::new(vec![0; 2 * ROM_BANK_SIZE], 4 * RAM_BANK_SIZE, true);
+    mbc.write_rom(0x0000, 0x0a);
+
+    mbc.write_rom(0x4000, 0b1001);
+    mbc.write_rom(0x4000, 0b1001);
+    mbc.write_ram(0xa000, 0x12);
+    mbc.write_rom(0x4000, 0b0001);
+
+    assert_eq!(
+      vec![CartridgeEvent::Rumble(true), CartridgeEvent::Rumble(false)],
+      mbc.drain_events()
+    );
+    assert!(mbc.drain_events().is_empty());
+    assert_eq!(0x12, mbc.read_ram(0xa000));
+  }
+}
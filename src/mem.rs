@@ -1,3 +1,18 @@
+use std::error::Error;
+use std::fmt;
+
+// Access of an address the device does not map, the bus routed it to the wrong device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnmappedAddress(pub u16);
+
+impl fmt::Display for UnmappedAddress {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Unmapped address: 0x{:>04x}", self.0)
+  }
+}
+
+impl Error for UnmappedAddress {}
+
 #[derive(Default)]
 pub struct Mem {
   mem: Vec<u8>,
@@ -12,7 +27,7 @@ impl Mem {
     self.mem = vec![0; 0x10000];
   }
 
-  pub fn write_word(&mut self, addr: u16, w: u8) {
+  pub fn write_word(&mut self, addr: u16, w: u8) -> Result<(), UnmappedAddress> {
     match addr {
       0xa000...0xbfff => self.mem[addr as usize] = w,
       0xe000...0xfdff => {
@@ -29,8 +44,9 @@ impl Mem {
       0xff00...0xff4b | 0xff80...0xffff => {
         self.mem[addr as usize] = w;
       }
-      _ => unimplemented!("Memory write to 0x{:x} is not implemented.", addr),
+      _ => return Err(UnmappedAddress(addr)),
     };
+    Ok(())
   }
 
   pub fn read_word(&self, addr: u16) -> u8 {
@@ -42,7 +58,14 @@ impl Mem {
 fn test_echo_mem() {
   let mut m = Mem::new();
   m.reset();
-  m.write_word(0xc000, 12);
+  m.write_word(0xc000, 12).unwrap();
   assert!(m.read_word(0xc000) == 12);
   assert!(m.read_word(0xe000) == 12);
 }
+
+#[test]
+fn test_unmapped_write() {
+  let mut m = Mem::new();
+  m.reset();
+  assert_eq!(Err(UnmappedAddress(0x8000)), m.write_word(0x8000, 12));
+}
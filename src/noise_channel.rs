@@ -0,0 +1,175 @@
+use super::channel::*;
+
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+// Channel 4, plays the output of a 15 (or 7) bit linear feedback shift register.
+#[derive(Debug)]
+pub struct NoiseChannel {
+  is_enabled: bool,
+  // NR43: clock shift (bits 4-7), width mode (bit 3) and divisor code (bits 0-2).
+  nr43: u8,
+  lfsr: u16,
+  // T-cycles until the next LFSR shift.
+  timer: u32,
+  envelope: Envelope,
+  length: LengthCounter,
+}
+
+impl Default for NoiseChannel {
+  fn default() -> NoiseChannel {
+    NoiseChannel::new()
+  }
+}
+
+impl NoiseChannel {
+  pub fn new() -> NoiseChannel {
+    NoiseChannel {
+      is_enabled: false,
+      nr43: 0,
+      lfsr: 0x7fff,
+      timer: DIVISORS[0],
+      envelope: Envelope::default(),
+      length: LengthCounter::new(64),
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.is_enabled
+  }
+
+  pub fn is_dac_enabled(&self) -> bool {
+    self.envelope.is_dac_enabled()
+  }
+
+  // Digital output, 0 - 15. The inverted bit 0 of the LFSR.
+  pub fn output(&self) -> u8 {
+    if !self.is_enabled {
+      return 0;
+    }
+
+    (!self.lfsr & 0b1) as u8 * self.envelope.volume()
+  }
+
+  // Clears the registers, the length counter keeps running on the DMG.
+  pub fn power_off(&mut self) {
+    self.length.set_enabled(false, true);
+
+    *self = NoiseChannel {
+      length: self.length,
+      ..NoiseChannel::new()
+    };
+  }
+
+  pub fn read_nr42(&self) -> u8 {
+    self.envelope.read()
+  }
+
+  pub fn read_nr43(&self) -> u8 {
+    self.nr43
+  }
+
+  pub fn read_nr44(&self) -> u8 {
+    (self.length.is_enabled() as u8) << 6
+  }
+
+  // NR41: length (bits 0-5).
+  pub fn write_nr41(&mut self, w: u8) {
+    self.length.load(w & 0b11_1111);
+  }
+
+  // NR42: volume envelope.
+  pub fn write_nr42(&mut self, w: u8) {
+    self.envelope.write(w);
+    if !self.is_dac_enabled() {
+      self.is_enabled = false;
+    }
+  }
+
+  pub fn write_nr43(&mut self, w: u8) {
+    self.nr43 = w;
+  }
+
+  // NR44: trigger (bit 7) and length enable (bit 6).
+  pub fn write_nr44(&mut self, w: u8, is_length_clocked_next: bool) {
+    if self
+      .length
+      .set_enabled(bitn!(w, 6) == 0x1, is_length_clocked_next)
+    {
+      self.is_enabled = false;
+    }
+
+    if bitn!(w, 7) == 0x1 {
+      self.trigger(is_length_clocked_next);
+    }
+  }
+
+  pub fn tick(&mut self, cycles: u32) {
+    let mut cycles = cycles;
+    while cycles >= self.timer {
+      cycles -= self.timer;
+      self.timer = self.period();
+      self.shift_lfsr();
+    }
+    self.timer -= cycles;
+  }
+
+  pub fn clock_length(&mut self) {
+    if self.length.clock() {
+      self.is_enabled = false;
+    }
+  }
+
+  pub fn clock_envelope(&mut self) {
+    self.envelope.clock();
+  }
+
+  fn trigger(&mut self, is_length_clocked_next: bool) {
+    self.is_enabled = self.is_dac_enabled();
+    self.timer = self.period();
+    self.lfsr = 0x7fff;
+    self.envelope.trigger();
+    self.length.trigger(is_length_clocked_next);
+  }
+
+  // The XOR of the lowest 2 bits is shifted in at bit 14 (and also at bit 6 in 7 bit mode).
+  fn shift_lfsr(&mut self) {
+    let xor = (self.lfsr & 0b1) ^ ((self.lfsr >> 1) & 0b1);
+    self.lfsr = (self.lfsr >> 1) | (xor << 14);
+
+    if self.is_width_7() {
+      self.lfsr = (self.lfsr & !(1 << 6)) | (xor << 6);
+    }
+  }
+
+  fn is_width_7(&self) -> bool {
+    bitn!(self.nr43, 3) == 0x1
+  }
+
+  // T-cycles of one LFSR shift.
+  fn period(&self) -> u32 {
+    DIVISORS[(self.nr43 & 0b111) as usize] << (self.nr43 >> 4)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_lfsr_width_7() {
+    let mut channel = NoiseChannel::new();
+    channel.write_nr42(0xf0);
+    channel.write_nr43(0b0000_1000);
+    channel.write_nr44(0x80, true);
+
+    // The 7 bit LFSR repeats every 127 shifts.
+    let mut outputs = vec![];
+    for _ in 0..254 {
+      outputs.push(channel.output());
+      channel.tick(DIVISORS[0]);
+    }
+    assert_eq!(outputs[..127], outputs[127..]);
+    assert!(outputs[..127].iter().any(|&output| output == 0));
+    assert!(outputs[..127].iter().any(|&output| output == 0xf));
+  }
+}
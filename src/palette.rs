@@ -0,0 +1,152 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+  pub r: u8,
+  pub g: u8,
+  pub b: u8,
+}
+
+impl Rgb {
+  pub const fn new(r: u8, g: u8, b: u8) -> Rgb {
+    Rgb { r, g, b }
+  }
+
+  // Format: RRGGBB, optionally prefixed with '#'.
+  pub fn parse(s: &str) -> Option<Rgb> {
+    let hex = s.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+      return None;
+    }
+
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some(Rgb::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+  }
+}
+
+#[derive(Debug)]
+pub struct InvalidPaletteError(pub String);
+
+impl fmt::Display for InvalidPaletteError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "Invalid palette: '{}', expected grayscale, classic_green, pocket_grey or 4 RRGGBB colors",
+      self.0
+    )
+  }
+}
+
+impl Error for InvalidPaletteError {}
+
+// Maps the 4 shades of the DMG (white to black) to the displayed colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputPalette {
+  pub colors: [Rgb; 4],
+}
+
+impl OutputPalette {
+  pub fn grayscale() -> OutputPalette {
+    OutputPalette {
+      colors: [
+        Rgb::new(255, 255, 255),
+        Rgb::new(170, 170, 170),
+        Rgb::new(85, 85, 85),
+        Rgb::new(0, 0, 0),
+      ],
+    }
+  }
+
+  // Original DMG screen.
+  pub fn classic_green() -> OutputPalette {
+    OutputPalette {
+      colors: [
+        Rgb::new(0x9b, 0xbc, 0x0f),
+        Rgb::new(0x8b, 0xac, 0x0f),
+        Rgb::new(0x30, 0x62, 0x30),
+        Rgb::new(0x0f, 0x38, 0x0f),
+      ],
+    }
+  }
+
+  // Game Boy Pocket screen.
+  pub fn pocket_grey() -> OutputPalette {
+    OutputPalette {
+      colors: [
+        Rgb::new(0xc4, 0xcf, 0xa1),
+        Rgb::new(0x8b, 0x95, 0x6d),
+        Rgb::new(0x4d, 0x53, 0x3c),
+        Rgb::new(0x1f, 0x1f, 0x1f),
+      ],
+    }
+  }
+
+  // Either a palette name or 4 colors (lightest first) separated by commas or spaces.
+  pub fn parse(s: &str) -> Result<OutputPalette, InvalidPaletteError> {
+    match s.trim() {
+      "grayscale" => return Ok(OutputPalette::grayscale()),
+      "classic_green" => return Ok(OutputPalette::classic_green()),
+      "pocket_grey" => return Ok(OutputPalette::pocket_grey()),
+      _ => {}
+    };
+
+    let colors: Vec<Rgb> = s
+      .split(|c: char| c == ',' || c.is_whitespace())
+      .filter(|part| !part.is_empty())
+      .map(Rgb::parse)
+      .collect::<Option<Vec<Rgb>>>()
+      .ok_or_else(|| InvalidPaletteError(s.to_owned()))?;
+
+    if colors.len() != 4 {
+      return Err(InvalidPaletteError(s.to_owned()));
+    }
+
+    Ok(OutputPalette {
+      colors: [colors[0], colors[1], colors[2], colors[3]],
+    })
+  }
+
+  pub fn color(&self, shade: u8) -> Rgb {
+    self.colors[(shade & 0b11) as usize]
+  }
+}
+
+impl Default for OutputPalette {
+  fn default() -> OutputPalette {
+    OutputPalette::grayscale()
+  }
+}
+
+// Maps a 2 bit color code through a BGP/OBP0/OBP1 register to a shade.
+pub fn apply_palette_register(palette: u8, color_code: u8) -> u8 {
+  (palette >> ((color_code & 0b11) << 1)) & 0b11
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_apply_palette_register() {
+    assert_eq!(0b00, apply_palette_register(0b1110_0100, 0b00));
+    assert_eq!(0b10, apply_palette_register(0b1110_0100, 0b10));
+    assert_eq!(0b11, apply_palette_register(0b1110_0100, 0b11));
+    // Inverted.
+    assert_eq!(0b11, apply_palette_register(0b0001_1011, 0b00));
+  }
+
+  #[test]
+  fn test_parse() {
+    assert_eq!(
+      OutputPalette::classic_green(),
+      OutputPalette::parse("classic_green").unwrap()
+    );
+
+    let palette = OutputPalette::parse("#ffffff, aaaaaa,555555 #000000").unwrap();
+    assert_eq!(OutputPalette::grayscale(), palette);
+
+    assert!(OutputPalette::parse("ffffff,aaaaaa,555555").is_err());
+    assert!(OutputPalette::parse("purple").is_err());
+  }
+}
@@ -0,0 +1,159 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Storage shared by the two ends, one slot always stays empty to tell a full buffer from an empty one.
+struct Shared<T> {
+  slots: Box<[UnsafeCell<T>]>,
+  // Next slot to read, only moved by the consumer.
+  head: AtomicUsize,
+  // Next slot to write, only moved by the producer.
+  tail: AtomicUsize,
+}
+
+// The slots between head and tail belong to the consumer, the rest to the producer.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+  fn len(&self) -> usize {
+    let head = self.head.load(Ordering::Acquire);
+    let tail = self.tail.load(Ordering::Acquire);
+    (tail + self.slots.len() - head) % self.slots.len()
+  }
+}
+
+// Writing end of a lock-free single producer, single consumer queue.
+pub struct Producer<T> {
+  shared: Arc<Shared<T>>,
+}
+
+// Reading end of a lock-free single producer, single consumer queue.
+pub struct Consumer<T> {
+  shared: Arc<Shared<T>>,
+}
+
+pub fn ring_buffer<T: Copy + Default>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+  let slots: Vec<UnsafeCell<T>> = (0..capacity + 1)
+    .map(|_| UnsafeCell::new(T::default()))
+    .collect();
+  let shared = Arc::new(Shared {
+    slots: slots.into_boxed_slice(),
+    head: AtomicUsize::new(0),
+    tail: AtomicUsize::new(0),
+  });
+
+  (
+    Producer {
+      shared: shared.clone(),
+    },
+    Consumer { shared },
+  )
+}
+
+impl<T: Copy> Producer<T> {
+  // Returns the number of values pushed, the ones not fitting are dropped.
+  pub fn push(&mut self, values: &[T]) -> usize {
+    let shared = &*self.shared;
+    let size = shared.slots.len();
+    let head = shared.head.load(Ordering::Acquire);
+    let tail = shared.tail.load(Ordering::Relaxed);
+
+    let count = values.len().min((head + size - tail - 1) % size);
+    for (i, &value) in values[..count].iter().enumerate() {
+      unsafe {
+        *shared.slots[(tail + i) % size].get() = value;
+      }
+    }
+
+    shared.tail.store((tail + count) % size, Ordering::Release);
+    count
+  }
+
+  pub fn len(&self) -> usize {
+    self.shared.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.shared.slots.len() - 1
+  }
+}
+
+impl<T: Copy> Consumer<T> {
+  // Fills the start of `values`, returns the number of values popped.
+  pub fn pop(&mut self, values: &mut [T]) -> usize {
+    let shared = &*self.shared;
+    let size = shared.slots.len();
+    let head = shared.head.load(Ordering::Relaxed);
+    let tail = shared.tail.load(Ordering::Acquire);
+
+    let count = values.len().min((tail + size - head) % size);
+    for (i, value) in values[..count].iter_mut().enumerate() {
+      unsafe {
+        *value = *shared.slots[(head + i) % size].get();
+      }
+    }
+
+    shared.head.store((head + count) % size, Ordering::Release);
+    count
+  }
+
+  pub fn len(&self) -> usize {
+    self.shared.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use std::thread;
+
+  #[test]
+  fn test_push_pop() {
+    let (mut producer, mut consumer) = ring_buffer::<u8>(4);
+    assert_eq!(3, producer.push(&[1, 2, 3]));
+    assert_eq!(3, consumer.len());
+
+    let mut values = [0; 2];
+    assert_eq!(2, consumer.pop(&mut values));
+    assert_eq!([1, 2], values);
+
+    // Wraps around, the values not fitting are dropped.
+    assert_eq!(3, producer.push(&[4, 5, 6, 7]));
+    let mut values = [0; 8];
+    assert_eq!(4, consumer.pop(&mut values));
+    assert_eq!([3, 4, 5, 6], values[..4]);
+    assert!(consumer.is_empty());
+  }
+
+  #[test]
+  fn test_threads() {
+    let (mut producer, mut consumer) = ring_buffer::<u32>(16);
+
+    let writer = thread::spawn(move || {
+      let values: Vec<u32> = (0..1000).collect();
+      let mut pushed = 0;
+      while pushed < values.len() {
+        pushed += producer.push(&values[pushed..(pushed + 7).min(values.len())]);
+      }
+    });
+
+    let mut expected = 0;
+    let mut values = [0; 5];
+    while expected < 1000 {
+      let count = consumer.pop(&mut values);
+      for &value in values[..count].iter() {
+        assert_eq!(expected, value);
+        expected += 1;
+      }
+    }
+    writer.join().unwrap();
+  }
+}
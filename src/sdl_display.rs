@@ -0,0 +1,143 @@
+use super::display_adapter::*;
+use super::palette::*;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::{Texture, TextureValueError, WindowCanvas};
+use sdl2::ttf;
+use sdl2::video::WindowBuildError;
+use sdl2::{IntegerOrSdlError, Sdl};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+
+const SCALE: u32 = 2;
+
+// Setting up the windows, the audio device or the debugger failed.
+#[derive(Debug)]
+pub enum SdlError {
+  Init(String),
+  Io(io::Error),
+}
+
+impl fmt::Display for SdlError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SdlError::Init(err) => write!(f, "Cannot initialize SDL: {}", err),
+      SdlError::Io(err) => write!(f, "Cannot create file: {}", err),
+    }
+  }
+}
+
+impl Error for SdlError {}
+
+impl From<String> for SdlError {
+  fn from(err: String) -> SdlError {
+    SdlError::Init(err)
+  }
+}
+
+impl From<WindowBuildError> for SdlError {
+  fn from(err: WindowBuildError) -> SdlError {
+    SdlError::Init(err.to_string())
+  }
+}
+
+impl From<IntegerOrSdlError> for SdlError {
+  fn from(err: IntegerOrSdlError) -> SdlError {
+    SdlError::Init(err.to_string())
+  }
+}
+
+impl From<TextureValueError> for SdlError {
+  fn from(err: TextureValueError) -> SdlError {
+    SdlError::Init(err.to_string())
+  }
+}
+
+impl From<ttf::InitError> for SdlError {
+  fn from(err: ttf::InitError) -> SdlError {
+    SdlError::Init(err.to_string())
+  }
+}
+
+impl From<io::Error> for SdlError {
+  fn from(err: io::Error) -> SdlError {
+    SdlError::Io(err)
+  }
+}
+
+pub fn sdl_color(rgb: Rgb) -> Color {
+  Color::RGB(rgb.r, rgb.g, rgb.b)
+}
+
+// SDL frontend of the PPU, each frame is uploaded into a single streaming texture.
+pub struct SdlDisplay {
+  canvas: WindowCanvas,
+  texture: Texture,
+  output_palette: OutputPalette,
+}
+
+impl SdlDisplay {
+  // With vsync presenting a frame waits for the display refresh.
+  pub fn new(sdl: Rc<Sdl>, is_vsync_enabled: bool) -> Result<SdlDisplay, SdlError> {
+    let video_subsystem = sdl.video()?;
+    let window = video_subsystem
+      .window(
+        "Y.A.G.B.E.",
+        SCREEN_WIDTH as u32 * SCALE,
+        SCREEN_HEIGHT as u32 * SCALE,
+      )
+      .position(16, 64)
+      .opengl()
+      .build()?;
+
+    let canvas_builder = window.into_canvas();
+    let canvas_builder = if is_vsync_enabled {
+      canvas_builder.present_vsync()
+    } else {
+      canvas_builder
+    };
+    let mut canvas = canvas_builder.build()?;
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.clear();
+    canvas.present();
+
+    let texture = canvas.texture_creator().create_texture_streaming(
+      PixelFormatEnum::RGB24,
+      SCREEN_WIDTH as u32,
+      SCREEN_HEIGHT as u32,
+    )?;
+
+    Ok(SdlDisplay {
+      canvas,
+      texture,
+      output_palette: OutputPalette::default(),
+    })
+  }
+}
+
+impl VideoSink for SdlDisplay {
+  fn frame_ready(&mut self, framebuffer: &Framebuffer) {
+    let output_palette = self.output_palette;
+    let _ = self
+      .texture
+      .with_lock(None, |buffer: &mut [u8], pitch: usize| {
+        for y in 0..SCREEN_HEIGHT {
+          for x in 0..SCREEN_WIDTH {
+            let rgb = output_palette.color(framebuffer[y * SCREEN_WIDTH + x]);
+            let offs = y * pitch + x * 3;
+            buffer[offs] = rgb.r;
+            buffer[offs + 1] = rgb.g;
+            buffer[offs + 2] = rgb.b;
+          }
+        }
+      });
+
+    let _ = self.canvas.copy(&self.texture, None, None);
+    self.canvas.present();
+  }
+
+  fn set_output_palette(&mut self, output_palette: OutputPalette) {
+    self.output_palette = output_palette;
+  }
+}
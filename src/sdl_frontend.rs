@@ -0,0 +1,490 @@
+use super::cartridge::*;
+use super::debugger::*;
+use super::display_adapter::*;
+use super::emu::*;
+use super::input::*;
+use super::palette::*;
+use super::ring_buffer::*;
+use super::sdl_display::*;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::controller::{self, Axis, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::{EventPump, GameControllerSubsystem, Sdl};
+use std::collections::HashMap;
+use std::io::{stdout, Write};
+use std::rc::Rc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const SAMPLE_RATE: i32 = 44_100;
+// 100 ms of stereo samples at up to 48 kHz, the samples not fitting are dropped so the latency stays bounded.
+const AUDIO_BUFFER_SIZE: usize = 48_000 / 10 * 2;
+// Audio pacing keeps this much audio buffered.
+const TARGET_AUDIO_LATENCY_MS: usize = 40;
+// The sample rate is stretched by at most 0.5% to keep the audio buffer level, too little to hear the pitch change.
+const MAX_AUDIO_RATE_ADJUSTMENT: f64 = 0.005;
+// The motor stays on until the cartridge turns it off, SDL needs a limit though.
+const RUMBLE_DURATION_MS: u32 = 10_000;
+// 70224 t-cycles, 16.74 ms.
+const FRAME_TIME: Duration = Duration::from_nanos(CYCLES_PER_FRAME * 1_000_000_000 / CPU_FREQUENCY);
+
+// How the emulation is kept at the Game Boy speed (59.73 frames per second).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pacing {
+  // Waits for the sound card to play the buffered audio. Without sound (muted or in STOP mode) a timer is used.
+  Audio,
+  // Presents the frames at the display refresh, the emulation runs at the refresh rate (eg 60 Hz).
+  Vsync,
+  // As fast as possible, for benchmarking. The audio not fitting the buffer is dropped.
+  Unthrottled,
+}
+
+// Plays the samples of the emulation, silence when it falls behind.
+struct AudioOutput {
+  samples: Consumer<f32>,
+}
+
+impl AudioCallback for AudioOutput {
+  type Channel = f32;
+
+  fn callback(&mut self, out: &mut [f32]) {
+    let count = self.samples.pop(out);
+    for sample in out[count..].iter_mut() {
+      *sample = 0.0;
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PadInput {
+  Button(controller::Button),
+  // Axis with the direction, true is positive.
+  Axis(Axis, bool),
+}
+
+impl PadInput {
+  // Button name or direction prefixed axis name.
+  fn parse(name: &str) -> Option<PadInput> {
+    if name.starts_with('+') || name.starts_with('-') {
+      Axis::from_string(&name[1..]).map(|axis| PadInput::Axis(axis, name.starts_with('+')))
+    } else {
+      controller::Button::from_string(name).map(PadInput::Button)
+    }
+  }
+}
+
+// An open game controller with its resolved bindings.
+struct Pad {
+  controller: GameController,
+  inputs: Vec<(Button, PadInput)>,
+  // Axis values within this distance from the center are ignored.
+  deadzone: i16,
+}
+
+impl Pad {
+  fn new(controller: GameController, bindings: &PadBindings) -> Pad {
+    let mut pad = Pad {
+      controller,
+      inputs: vec![],
+      deadzone: 0,
+    };
+    pad.bind(bindings);
+    pad
+  }
+
+  fn bind(&mut self, bindings: &PadBindings) {
+    self.inputs.clear();
+    for &button in Button::ALL.iter() {
+      for name in bindings.inputs(button) {
+        match PadInput::parse(name) {
+          Some(input) => self.inputs.push((button, input)),
+          None => warn!("Unknown controller input: '{}'", name),
+        }
+      }
+    }
+
+    self.deadzone = (bindings.deadzone * i16::MAX as f32) as i16;
+  }
+
+  // Adds the buttons held on the controller.
+  fn read_buttons(&self, buttons: &mut Buttons) {
+    for &(button, input) in self.inputs.iter() {
+      let is_pressed = match input {
+        PadInput::Button(pad_button) => self.controller.button(pad_button),
+        PadInput::Axis(axis, true) => self.controller.axis(axis) > self.deadzone,
+        PadInput::Axis(axis, false) => self.controller.axis(axis) < -self.deadzone,
+      };
+
+      if is_pressed {
+        buttons.set(button, true);
+      }
+    }
+  }
+}
+
+// Window, speaker, keyboard and game controllers for the emulator. Drives the emulation a frame at a time, or an instruction at a time
+// in debug mode.
+pub struct SdlFrontend {
+  sdl: Rc<Sdl>,
+  display: SdlDisplay,
+  event_pump: EventPump,
+  audio_device: AudioDevice<AudioOutput>,
+  audio_samples: Producer<f32>,
+  // Buffered samples the audio pacing and rate adjustment aim for.
+  target_audio_samples: usize,
+  pacing: Pacing,
+  // Deadline of the next frame with timer pacing.
+  next_frame: Instant,
+  controller_subsystem: GameControllerSubsystem,
+  debugger: Option<Debugger>,
+  key_map: HashMap<Keycode, Button>,
+  pad_config: PadConfig,
+  // Connected controllers by joystick instance id.
+  pads: HashMap<i32, Pad>,
+  // Held on the keyboard.
+  buttons: Buttons,
+  should_quit: bool,
+  iteration_count: u64,
+}
+
+impl SdlFrontend {
+  pub fn new() -> Result<SdlFrontend, SdlError> {
+    SdlFrontend::with_pacing(Pacing::Audio)
+  }
+
+  // Vsync can only be set up with the window.
+  pub fn with_pacing(pacing: Pacing) -> Result<SdlFrontend, SdlError> {
+    let sdl = Rc::new(sdl2::init()?);
+
+    let desired_spec = AudioSpecDesired {
+      freq: Some(SAMPLE_RATE),
+      channels: Some(2),
+      samples: None,
+    };
+    let (audio_samples, samples) = ring_buffer(AUDIO_BUFFER_SIZE);
+    let audio_device = sdl
+      .audio()?
+      .open_playback(None, &desired_spec, |_| AudioOutput { samples })?;
+    audio_device.resume();
+    let target_audio_samples =
+      audio_device.spec().freq as usize * 2 * TARGET_AUDIO_LATENCY_MS / 1000;
+
+    let mut frontend = SdlFrontend {
+      display: SdlDisplay::new(sdl.clone(), pacing == Pacing::Vsync)?,
+      event_pump: sdl.event_pump()?,
+      audio_device,
+      audio_samples,
+      target_audio_samples,
+      pacing,
+      next_frame: Instant::now(),
+      // Already connected controllers are reported as added with the first events.
+      controller_subsystem: sdl.game_controller()?,
+      sdl,
+      debugger: None,
+      key_map: HashMap::new(),
+      pad_config: PadConfig::default(),
+      pads: HashMap::new(),
+      buttons: Buttons::default(),
+      should_quit: false,
+      iteration_count: 0u64,
+    };
+    frontend.set_key_bindings(&KeyBindings::default());
+    Ok(frontend)
+  }
+
+  pub fn enable_debug_mode(&mut self, emu: &Emu) -> Result<(), SdlError> {
+    self.debugger = Some(Debugger::new(self.sdl.clone(), emu.cpu.pc)?);
+    Ok(())
+  }
+
+  pub fn set_output_palette(&mut self, output_palette: OutputPalette) {
+    self.display.set_output_palette(output_palette);
+  }
+
+  // Buttons with an unknown key name keep their default key.
+  pub fn set_key_bindings(&mut self, key_bindings: &KeyBindings) {
+    let default_bindings = KeyBindings::default();
+
+    self.key_map.clear();
+    for &button in Button::ALL.iter() {
+      let keycode = Keycode::from_name(key_bindings.key(button)).or_else(|| {
+        warn!("Unknown key name: '{}'", key_bindings.key(button));
+        Keycode::from_name(default_bindings.key(button))
+      });
+
+      if let Some(keycode) = keycode {
+        self.key_map.insert(keycode, button);
+      }
+    }
+  }
+
+  pub fn set_pad_config(&mut self, pad_config: PadConfig) {
+    self.pad_config = pad_config;
+    for pad in self.pads.values_mut() {
+      pad.bind(&self.pad_config.bindings_for(&pad.controller.name()));
+    }
+  }
+
+  // Returns the emulation error that stopped the run. With the debugger enabled errors are reported and the debugger
+  // takes over instead.
+  pub fn run(&mut self, emu: &mut Emu) -> Result<(), EmuError> {
+    emu.set_audio_sample_rate(self.audio_device.spec().freq as u32);
+
+    let start = Instant::now();
+    let start_frame_count = emu.frame_count();
+
+    let result = self.run_until_quit(emu);
+    emu.flush_battery_save();
+
+    if self.pacing == Pacing::Unthrottled {
+      print_speed(emu.frame_count() - start_frame_count, start.elapsed());
+    }
+    result
+  }
+
+  fn run_until_quit(&mut self, emu: &mut Emu) -> Result<(), EmuError> {
+    while !self.should_quit {
+      let result = if self.debugger.is_some() {
+        self.run_debugged_frame(emu)
+      } else {
+        emu.run_frame()
+      };
+
+      if let Err(err) = result {
+        if self.debugger.is_none() {
+          return Err(err);
+        }
+
+        println!("[YAGBE] -- Emulation error: {}", err);
+        self.operate_debugger(emu);
+      }
+
+      // Also presented in STOP mode, the pacing goes on while waiting for a button press.
+      self.display.frame_ready(emu.framebuffer());
+      self.queue_audio(emu);
+      self.handle_cartridge_events(emu);
+      self.pace(emu);
+      self.print_serial_output(emu);
+      self.poll_events(emu);
+    }
+
+    Ok(())
+  }
+
+  fn run_debugged_frame(&mut self, emu: &mut Emu) -> Result<(), EmuError> {
+    let frame_count = emu.frame_count();
+
+    while emu.frame_count() == frame_count && !emu.is_stopped() {
+      let should_break = self.debugger.as_mut().unwrap().should_break(&emu.cpu);
+      if should_break {
+        self.operate_debugger(emu);
+      }
+
+      if self.should_quit {
+        return Ok(());
+      }
+
+      emu.step_instruction()?;
+
+      if self.iteration_count & 0xfff == 0 {
+        self.update_debug_windows(emu);
+      }
+
+      self.iteration_count += 1;
+    }
+
+    Ok(())
+  }
+
+  fn operate_debugger(&mut self, emu: &mut Emu) {
+    let command = self.debugger.as_mut().unwrap().read_command();
+    match command {
+      DebuggerCommand::Quit => {
+        self.should_quit = true;
+        return;
+      }
+      DebuggerCommand::MemoryPrint(addr, len) => mem_debug_print(emu, addr, len),
+      DebuggerCommand::CpuPrint => emu.cpu.registers_debug_print(),
+      DebuggerCommand::InterruptPrint => emu.interrupts.debug_print(),
+      DebuggerCommand::Breakpoint => { /* keep it stopped */ }
+      DebuggerCommand::Continue | DebuggerCommand::Next => {
+        self.update_debug_windows(emu);
+        return;
+      }
+      DebuggerCommand::Display => {
+        let mut console_display = ConsoleDisplay::default();
+        console_display.frame_ready(emu.framebuffer());
+        console_display.draw();
+      }
+      DebuggerCommand::PrintBackgroundMap => self.update_debug_windows(emu),
+      DebuggerCommand::History => self.debugger.as_ref().unwrap().print_history(),
+      _ => {}
+    };
+
+    self.operate_debugger(emu);
+  }
+
+  fn update_debug_windows(&mut self, emu: &Emu) {
+    if let Some(debugger) = self.debugger.as_mut() {
+      debugger.update_debug_windows(
+        self.iteration_count,
+        &emu.cpu,
+        &emu.graphics,
+        &emu.interrupts,
+      );
+    }
+  }
+
+  fn queue_audio(&mut self, emu: &mut Emu) {
+    let samples = emu.drain_audio();
+    self.audio_samples.push(&samples);
+  }
+
+  // The rumble motor of the cartridge rumbles the controllers.
+  fn handle_cartridge_events(&mut self, emu: &mut Emu) {
+    for event in emu.drain_cartridge_events() {
+      match event {
+        CartridgeEvent::Rumble(is_on) => {
+          let strength = if is_on { 0xffff } else { 0 };
+          for pad in self.pads.values_mut() {
+            if let Err(err) = pad
+              .controller
+              .set_rumble(strength, strength, RUMBLE_DURATION_MS)
+            {
+              debug!("Cannot rumble {}: {}", pad.controller.name(), err);
+            }
+          }
+        }
+      }
+    }
+  }
+
+  fn pace(&mut self, emu: &mut Emu) {
+    match self.pacing {
+      Pacing::Audio if emu.is_sound_muted() || emu.is_stopped() => self.wait_frame_time(),
+      Pacing::Audio => {
+        self.wait_audio_buffer();
+        self.adjust_audio_rate(emu);
+      }
+      // Presenting the frame waited for the refresh.
+      Pacing::Vsync => self.adjust_audio_rate(emu),
+      Pacing::Unthrottled => {}
+    }
+  }
+
+  // Sleeps until the buffered audio is down to the target latency, so the sound card clocks the emulation.
+  fn wait_audio_buffer(&mut self) {
+    while self.audio_samples.len() > self.target_audio_samples {
+      sleep(Duration::from_millis(1));
+    }
+    self.next_frame = Instant::now();
+  }
+
+  // Sleeps until the next frame is due, without catching up when running behind.
+  fn wait_frame_time(&mut self) {
+    self.next_frame += FRAME_TIME;
+
+    let now = Instant::now();
+    if self.next_frame > now {
+      sleep(self.next_frame - now);
+    } else {
+      self.next_frame = now;
+    }
+  }
+
+  // Generates slightly more samples when the audio buffer runs low and less when it fills up, so neither the
+  // differences of the sound card and emulation clocks nor vsync at the display rate cause under- or overruns.
+  fn adjust_audio_rate(&mut self, emu: &mut Emu) {
+    let level = self.audio_samples.len() as f64 / self.target_audio_samples as f64;
+    let rate_adjustment = 1.0 + MAX_AUDIO_RATE_ADJUSTMENT * (1.0 - level).clamp(-1.0, 1.0);
+    emu.set_audio_rate_adjustment(rate_adjustment);
+  }
+
+  fn print_serial_output(&mut self, emu: &mut Emu) {
+    let output = emu.drain_serial_output();
+    if !output.is_empty() {
+      print!("{}", String::from_utf8_lossy(&output));
+      let _ = stdout().flush();
+    }
+  }
+
+  fn poll_events(&mut self, emu: &mut Emu) {
+    let events: Vec<Event> = self.event_pump.poll_iter().collect();
+    for event in events {
+      match event {
+        Event::Quit { .. } => self.should_quit = true,
+        Event::KeyDown {
+          keycode: Some(keycode),
+          ..
+        } => {
+          if let Some(&button) = self.key_map.get(&keycode) {
+            self.buttons.set(button, true);
+          }
+        }
+        Event::KeyUp {
+          keycode: Some(keycode),
+          ..
+        } => {
+          if let Some(&button) = self.key_map.get(&keycode) {
+            self.buttons.set(button, false);
+          }
+        }
+        Event::ControllerDeviceAdded { which, .. } => self.open_controller(which),
+        Event::ControllerDeviceRemoved { which, .. } => {
+          if let Some(pad) = self.pads.remove(&which) {
+            info!("Controller disconnected: {}", pad.controller.name());
+          }
+        }
+        _ => {}
+      }
+    }
+
+    let mut buttons = self.buttons;
+    for pad in self.pads.values() {
+      pad.read_buttons(&mut buttons);
+    }
+    emu.set_buttons(buttons);
+  }
+
+  fn open_controller(&mut self, joystick_index: u32) {
+    match self.controller_subsystem.open(joystick_index) {
+      Ok(controller) => {
+        info!("Controller connected: {}", controller.name());
+        let bindings = self.pad_config.bindings_for(&controller.name());
+        self
+          .pads
+          .insert(controller.instance_id(), Pad::new(controller, &bindings));
+      }
+      Err(err) => warn!("Cannot open controller {}: {}", joystick_index, err),
+    }
+  }
+}
+
+fn print_speed(frame_count: u64, elapsed: Duration) {
+  let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+  let fps = frame_count as f64 / seconds;
+  println!(
+    "[YAGBE] -- {} frames in {:.2} s, {:.1} fps ({:.0}% speed)",
+    frame_count,
+    seconds,
+    fps,
+    fps / FRAME_RATE * 100.0
+  );
+}
+
+fn mem_debug_print(emu: &Emu, addr: u16, len: usize) {
+  for offs in 0..len {
+    if offs % 8 == 0 {
+      print!("\n[0x{:>04x}] ", addr.wrapping_add(offs as u16));
+    }
+
+    if offs % 4 == 0 {
+      print!(" ");
+    }
+
+    print!("{:>02x} ", emu.read_memory(addr.wrapping_add(offs as u16)));
+  }
+
+  println!();
+}
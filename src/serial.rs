@@ -1,16 +1,100 @@
+use super::mem::UnmappedAddress;
+
+// 8 bits at 8192 Hz with the internal clock.
+const TRANSFER_DURATION: u64 = 8 * 512;
+
 #[derive(Debug, Default)]
-pub struct Serial;
+pub struct SerialResult {
+  pub interrupt_generated: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Serial {
+  sb: u8,
+  sc: u8,
+  // Remaining t-cycles of the ongoing transfer.
+  transfer_timer: Option<u64>,
+  // Bytes sent out so far, there is no link partner connected.
+  output: Vec<u8>,
+}
 
 impl Serial {
-  pub fn write_word(&self, addr: u16, w: u8) {
-    // @TODO Implement.
-    // println!(
-    //   "Serial data was received: #0x{:>04x} -> 0x{:>02x} ({:?})",
-    //   addr, w, w as char
-    // );
-
-    if w != 0x81 {
-      print!("{}", w as char);
+  pub fn reset(&mut self) {
+    *self = Serial::default();
+  }
+
+  pub fn update(&mut self, cycles_prev: u64, cycles: u64) -> SerialResult {
+    let mut result = SerialResult::default();
+
+    if let Some(timer) = self.transfer_timer {
+      let elapsed = cycles - cycles_prev;
+      if timer > elapsed {
+        self.transfer_timer = Some(timer - elapsed);
+      } else {
+        // Without a partner 0xff is shifted in.
+        self.output.push(self.sb);
+        self.sb = 0xff;
+        self.sc &= 0b0111_1111;
+        self.transfer_timer = None;
+        result.interrupt_generated = true;
+      }
     }
+
+    result
+  }
+
+  pub fn read_word(&self, addr: u16) -> Result<u8, UnmappedAddress> {
+    match addr {
+      0xff01 => Ok(self.sb),
+      // Unused bits read as 1.
+      0xff02 => Ok(self.sc | 0b0111_1110),
+      _ => Err(UnmappedAddress(addr)),
+    }
+  }
+
+  pub fn write_word(&mut self, addr: u16, w: u8) -> Result<(), UnmappedAddress> {
+    match addr {
+      0xff01 => self.sb = w,
+      0xff02 => {
+        self.sc = w & 0b1000_0001;
+
+        // Only transfers with the internal clock can complete, an external clock never arrives.
+        if self.sc == 0b1000_0001 {
+          self.transfer_timer = Some(TRANSFER_DURATION);
+        } else {
+          self.transfer_timer = None;
+        }
+      }
+      _ => return Err(UnmappedAddress(addr)),
+    }
+    Ok(())
+  }
+
+  pub fn output(&self) -> &[u8] {
+    &self.output
+  }
+
+  pub fn drain_output(&mut self) -> Vec<u8> {
+    self.output.drain(..).collect()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_transfer() {
+    let mut serial = Serial::default();
+    serial.write_word(0xff01, b'A').unwrap();
+    serial.write_word(0xff02, 0x81).unwrap();
+
+    assert!(!serial.update(0, 4000).interrupt_generated);
+    assert!(serial.output().is_empty());
+
+    assert!(serial.update(4000, 4100).interrupt_generated);
+    assert_eq!(b"A", serial.output());
+    assert_eq!(0xff, serial.read_word(0xff01).unwrap());
+    assert_eq!(0x7f, serial.read_word(0xff02).unwrap());
   }
 }
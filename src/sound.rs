@@ -1,274 +1,474 @@
-use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
-use sdl2::Sdl;
-use std::rc::Rc;
-use std::sync::{Arc, Mutex};
-
-#[derive(Debug, Default)]
-struct SoundPacket {
-  pitch: f32,                   // 1.0 .. ~k
-  volume: f32,                  // 0.0 .. 1.0
-  envelope_sweep_length: usize, // 22050 = 1s
-  envelope_direction_down: bool,
-  waveform: f32, // 0.0 .. 1.0
-  restart: bool,
+use super::blip_buffer::*;
+use super::emu::CPU_FREQUENCY;
+use super::mem::UnmappedAddress;
+use super::noise_channel::*;
+use super::square_channel::*;
+use super::wave_channel::*;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+// The frame sequencer runs at 512 Hz.
+const FRAME_SEQUENCER_PERIOD: u32 = 8192;
+// The output is evaluated every M-cycle (~1 MHz), then resampled.
+const OUTPUT_PERIOD: u64 = 4;
+// DMG output capacitor charge kept per t-cycle.
+const HIGH_PASS_CHARGE: f64 = 0.999_958;
+
+// Bits read as 1 (write-only and unused), 0xff10 - 0xff2f.
+const READ_MASKS: [u8; 0x20] = [
+  0x80, 0x3f, 0x00, 0xff, 0xbf, // NR10 - NR14
+  0xff, 0x3f, 0x00, 0xff, 0xbf, // Unused, NR21 - NR24
+  0x7f, 0xff, 0x9f, 0xff, 0xbf, // NR30 - NR34
+  0xff, 0xff, 0x00, 0x00, 0xbf, // Unused, NR41 - NR44
+  0x00, 0x00, 0x70, // NR50 - NR52
+  0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, // Unused
+];
+
+// DMG output capacitor, removes the DC offset of the DACs.
+#[derive(Default)]
+struct HighPassFilter {
+  capacitor: f32,
 }
 
-impl SoundPacket {
-  fn new(
-    pitch: f32,
-    volume: f32,
-    envelope_sweep_length: usize,
-    envelope_direction_down: bool,
-    waveform: f32,
-  ) -> SoundPacket {
-    SoundPacket {
-      pitch,
-      volume,
-      envelope_sweep_length,
-      envelope_direction_down,
-      waveform,
-      restart: true,
-    }
+impl HighPassFilter {
+  fn filter(&mut self, input: f32, charge: f32) -> f32 {
+    let output = input - self.capacitor;
+    self.capacitor = input - output * charge;
+    output
   }
 }
 
-struct SquareWave {
-  freq: f32,
-  phase: f32,
-  pocket: Arc<Mutex<Option<SoundPacket>>>,
-  envelope_sweep_counter: usize,
+// Cycle driven APU: the channels are clocked with the emulated t-cycles, the stereo output is band-limited resampled
+// to the sample rate (44.1 kHz by default).
+pub struct Sound {
+  channel1: SquareChannel,
+  channel2: SquareChannel,
+  channel3: WaveChannel,
+  channel4: NoiseChannel,
+
+  nr50: u8,
+  nr51: u8,
+  is_powered: bool,
+
+  frame_sequencer_timer: u32,
+  // 0 - 7, length counters are clocked on even steps, sweep on 2 and 6, envelopes on 7.
+  frame_sequencer_step: u8,
+
+  // Buffered until the frontend drains them, a second at most.
+  left: BlipBuffer,
+  right: BlipBuffer,
+  // Left and right amplitude given to the blip buffers.
+  output: (f32, f32),
+  high_pass_filters: [HighPassFilter; 2],
+  // Per output sample.
+  high_pass_charge: f32,
+  sample_rate: u32,
+  // Slightly stretches the sample rate, the frontend uses it to keep the fill level of its audio buffer.
+  rate_adjustment: f64,
+
+  muted: bool,
 }
 
-impl AudioCallback for SquareWave {
-  type Channel = f32;
+impl Default for Sound {
+  fn default() -> Sound {
+    Sound::new()
+  }
+}
 
-  fn callback(&mut self, out: &mut [f32]) {
-    for x in out.iter_mut() {
-      let mut pocket = self.pocket.lock().unwrap();
+impl Sound {
+  pub fn new() -> Sound {
+    Sound {
+      channel1: SquareChannel::with_sweep(),
+      channel2: SquareChannel::new(),
+      channel3: WaveChannel::new(),
+      channel4: NoiseChannel::new(),
 
-      *x = if pocket.is_some() {
-        if (*pocket).as_ref().unwrap().restart {
-          (*pocket).as_mut().unwrap().restart = false;
-          self.envelope_sweep_counter = (*pocket).as_mut().unwrap().envelope_sweep_length;
-        }
+      nr50: 0,
+      nr51: 0,
+      is_powered: false,
 
-        let pitch = pocket.as_ref().unwrap().pitch;
-        self.phase = (self.phase + (pitch / self.freq)) % 1.0;
-
-        if (*pocket).as_ref().unwrap().envelope_sweep_length > 0 {
-          if self.envelope_sweep_counter > 0 {
-            self.envelope_sweep_counter -= 1;
-          } else {
-            (*pocket).as_mut().unwrap().volume +=
-              if (*pocket).as_mut().unwrap().envelope_direction_down {
-                -1f32 / 15f32
-              } else {
-                1f32 / 15f32
-              };
-            self.envelope_sweep_counter = (*pocket).as_mut().unwrap().envelope_sweep_length;
-          }
-        }
+      frame_sequencer_timer: FRAME_SEQUENCER_PERIOD,
+      frame_sequencer_step: 0,
 
-        if (*pocket).as_ref().unwrap().volume < 0f32 {
-          (*pocket).as_mut().unwrap().volume = 0.0;
-        } else if (*pocket).as_ref().unwrap().volume > 1f32 {
-          (*pocket).as_mut().unwrap().volume = 1.0;
-        }
+      left: BlipBuffer::new(CPU_FREQUENCY as f64, DEFAULT_SAMPLE_RATE as f64),
+      right: BlipBuffer::new(CPU_FREQUENCY as f64, DEFAULT_SAMPLE_RATE as f64),
+      output: (0.0, 0.0),
+      high_pass_filters: Default::default(),
+      high_pass_charge: high_pass_charge(DEFAULT_SAMPLE_RATE),
+      sample_rate: DEFAULT_SAMPLE_RATE,
+      rate_adjustment: 1.0,
 
-        if self.phase <= pocket.as_ref().unwrap().waveform {
-          pocket.as_ref().unwrap().volume
-        } else {
-          -pocket.as_ref().unwrap().volume
-        }
-      } else {
-        0.0
-      };
+      muted: false,
     }
   }
-}
 
-pub struct Sound {
-  pub nr10: u8,
-  pub nr11: u8,
-  pub nr12: u8,
-  pub nr13: u8,
-  pub nr14: u8,
+  pub fn reset(&mut self) {
+    let muted = self.muted;
+    let sample_rate = self.sample_rate;
+    *self = Sound::new();
+    self.muted = muted;
+    self.set_sample_rate(sample_rate);
+  }
 
-  nr21: u8,
-  nr22: u8,
-  nr23: u8,
-  nr24: u8,
+  pub fn set_sample_rate(&mut self, sample_rate: u32) {
+    self.sample_rate = sample_rate;
+    self.high_pass_charge = high_pass_charge(sample_rate);
+    self.update_resampling_rates();
+  }
 
-  nr30: bool,
+  // Generates `rate_adjustment` times the samples of the sample rate.
+  pub fn set_rate_adjustment(&mut self, rate_adjustment: f64) {
+    self.rate_adjustment = rate_adjustment;
+    self.update_resampling_rates();
+  }
 
-  nr41: u8,
-  nr42: u8,
-  nr43: u8,
-  nr44: u8,
+  // The boot ROM leaves channel 1 running with the end of its chime.
+  pub fn reset_post_boot(&mut self) {
+    self.write_register(0xff26, 0xf1);
+
+    self.write_register(0xff10, 0x80);
+    self.write_register(0xff11, 0xbf);
+    self.write_register(0xff12, 0xf3);
+    self.write_register(0xff13, 0xff);
+    self.write_register(0xff14, 0xbf);
+
+    self.write_register(0xff16, 0x3f);
+    self.write_register(0xff17, 0x00);
+    self.write_register(0xff18, 0xff);
+    self.write_register(0xff19, 0xbf);
+
+    self.write_register(0xff1a, 0x7f);
+    self.write_register(0xff1b, 0xff);
+    self.write_register(0xff1c, 0x9f);
+    self.write_register(0xff1d, 0xff);
+    self.write_register(0xff1e, 0xbf);
+
+    self.write_register(0xff20, 0xff);
+    self.write_register(0xff21, 0x00);
+    self.write_register(0xff22, 0x00);
+    self.write_register(0xff23, 0xbf);
+
+    self.write_register(0xff24, 0x77);
+    self.write_register(0xff25, 0xf3);
+  }
 
-  pub nr50: u8,
-  pub nr51: u8,
-  pub nr52: u8,
+  pub fn update(&mut self, cycles_prev: u64, cycles: u64) {
+    let elapsed = cycles - cycles_prev;
 
-  audio_device: AudioDevice<SquareWave>,
+    // Advanced in steps ending at frame sequencer clocks and output points.
+    let mut clock = 0;
+    while clock < elapsed {
+      let step = (elapsed - clock)
+        .min(OUTPUT_PERIOD)
+        .min(self.frame_sequencer_timer as u64);
+      clock += step;
 
-  channel1_out: Arc<Mutex<Option<SoundPacket>>>,
+      if self.is_powered {
+        self.tick_channels(step as u32);
 
-  muted: bool,
-}
+        self.frame_sequencer_timer -= step as u32;
+        if self.frame_sequencer_timer == 0 {
+          self.frame_sequencer_timer = FRAME_SEQUENCER_PERIOD;
+          self.clock_frame_sequencer();
+        }
+      }
 
-impl Sound {
-  pub fn new(sdl: Rc<Sdl>) -> Sound {
-    let desired_spec = AudioSpecDesired {
-      freq: Some(44_100),
-      channels: Some(1),
-      samples: None,
-    };
+      if !self.muted {
+        self.output(clock);
+      }
+    }
 
-    let pocket = Arc::new(Mutex::new(None));
+    if !self.muted {
+      self.left.end_frame(elapsed);
+      self.right.end_frame(elapsed);
+    }
+  }
 
-    let device = sdl
-      .audio()
-      .unwrap()
-      .open_playback(None, &desired_spec, |spec| SquareWave {
-        freq: spec.freq as f32,
-        phase: 0.5,
-        pocket: pocket.clone(),
-        envelope_sweep_counter: 0,
-      })
-      .unwrap();
-    device.resume();
+  // Samples generated since the last call, interleaved stereo (left, right).
+  pub fn drain_samples(&mut self) -> Vec<f32> {
+    let left = self.left.read_samples();
+    let right = self.right.read_samples();
 
-    Sound {
-      nr10: 0,
-      nr11: 0,
-      nr12: 0,
-      nr13: 0,
-      nr14: 0,
+    let mut samples = Vec::with_capacity(left.len() * 2);
+    for (&left, &right) in left.iter().zip(right.iter()) {
+      samples.push(self.high_pass_filters[0].filter(left, self.high_pass_charge));
+      samples.push(self.high_pass_filters[1].filter(right, self.high_pass_charge));
+    }
+    samples
+  }
 
-      nr21: 0,
-      nr22: 0,
-      nr23: 0,
-      nr24: 0,
+  pub fn write_word(&mut self, addr: u16, w: u8) -> Result<(), UnmappedAddress> {
+    match addr {
+      0xff10...0xff3f => {
+        self.write_register(addr, w);
+        Ok(())
+      }
+      _ => Err(UnmappedAddress(addr)),
+    }
+  }
 
-      nr30: false,
+  fn write_register(&mut self, addr: u16, w: u8) {
+    // While powered off only NR52, the wave RAM and (on the DMG) the lengths can be written.
+    let w = match addr {
+      _ if self.is_powered => w,
+      0xff11 | 0xff16 => w & 0b11_1111,
+      0xff1b | 0xff20 | 0xff26 | 0xff30...0xff3f => w,
+      _ => return,
+    };
 
-      nr41: 0,
-      nr42: 0,
-      nr43: 0,
-      nr44: 0,
+    match addr {
+      0xff10 => self.channel1.write_nrx0(w),
+      0xff11 => self.channel1.write_nrx1(w),
+      0xff12 => self.channel1.write_nrx2(w),
+      0xff13 => self.channel1.write_nrx3(w),
+      0xff14 => self.channel1.write_nrx4(w, self.is_length_clocked_next()),
+
+      0xff16 => self.channel2.write_nrx1(w),
+      0xff17 => self.channel2.write_nrx2(w),
+      0xff18 => self.channel2.write_nrx3(w),
+      0xff19 => self.channel2.write_nrx4(w, self.is_length_clocked_next()),
+
+      0xff1a => self.channel3.write_nr30(w),
+      0xff1b => self.channel3.write_nr31(w),
+      0xff1c => self.channel3.write_nr32(w),
+      0xff1d => self.channel3.write_nr33(w),
+      0xff1e => self.channel3.write_nr34(w, self.is_length_clocked_next()),
+
+      0xff20 => self.channel4.write_nr41(w),
+      0xff21 => self.channel4.write_nr42(w),
+      0xff22 => self.channel4.write_nr43(w),
+      0xff23 => self.channel4.write_nr44(w, self.is_length_clocked_next()),
 
-      nr50: 0,
-      nr51: 0,
-      nr52: 0,
+      0xff24 => self.nr50 = w,
+      0xff25 => self.nr51 = w,
+      0xff26 => self.write_nr52(w),
+      0xff30...0xff3f => self.channel3.write_wave_ram(addr as usize - 0xff30, w),
+      // Unused registers: 0xff15, 0xff1f and 0xff27-0xff2f.
+      _ => {}
+    };
+  }
 
-      audio_device: device,
-      channel1_out: pocket,
+  pub fn read_word(&self, addr: u16) -> Result<u8, UnmappedAddress> {
+    let value = match addr {
+      0xff10 => self.channel1.read_nrx0(),
+      0xff11 => self.channel1.read_nrx1(),
+      0xff12 => self.channel1.read_nrx2(),
+      0xff14 => self.channel1.read_nrx4(),
+
+      0xff16 => self.channel2.read_nrx1(),
+      0xff17 => self.channel2.read_nrx2(),
+      0xff19 => self.channel2.read_nrx4(),
+
+      0xff1a => self.channel3.read_nr30(),
+      0xff1c => self.channel3.read_nr32(),
+      0xff1e => self.channel3.read_nr34(),
+
+      0xff21 => self.channel4.read_nr42(),
+      0xff22 => self.channel4.read_nr43(),
+      0xff23 => self.channel4.read_nr44(),
+
+      0xff24 => self.nr50,
+      0xff25 => self.nr51,
+      0xff26 => self.read_nr52(),
+      0xff30...0xff3f => return Ok(self.channel3.read_wave_ram(addr as usize - 0xff30)),
+      // Write-only and unused registers.
+      0xff13 | 0xff15 | 0xff18 | 0xff1b | 0xff1d | 0xff1f | 0xff20 | 0xff27...0xff2f => 0,
+      _ => return Err(UnmappedAddress(addr)),
+    };
 
-      muted: false,
-    }
+    Ok(value | READ_MASKS[addr as usize - 0xff10])
   }
 
-  pub fn reset(&mut self) {
-    self.nr52 = 0x0;
+  pub fn mute(&mut self) {
+    self.muted = true;
   }
 
-  pub fn write_word(&mut self, addr: u16, w: u8) {
-    // println!("0x{:>04x} = 0b{:>08b}", addr, w);
-    match addr {
-      0xff10 => self.nr10 = w,
-      0xff11 => self.nr11 = w,
-      0xff12 => self.nr12 = w,
-      0xff13 => self.nr13 = w,
-      0xff14 => {
-        self.nr14 = w;
-        self.handle_channel_1_out();
-      }
+  pub fn is_muted(&self) -> bool {
+    self.muted
+  }
 
-      0xff16 => self.nr21 = w,
-      0xff17 => self.nr22 = w,
-      0xff18 => self.nr23 = w,
-      0xff19 => {
-        self.nr24 = w;
-        self.handle_channel_2_out();
-      }
+  fn update_resampling_rates(&mut self) {
+    let sample_rate = self.sample_rate as f64 * self.rate_adjustment;
+    self.left.set_rates(CPU_FREQUENCY as f64, sample_rate);
+    self.right.set_rates(CPU_FREQUENCY as f64, sample_rate);
+  }
 
-      0xff1a => self.nr30 = bitn!(w, 7) == 1,
+  fn tick_channels(&mut self, cycles: u32) {
+    self.channel1.tick(cycles);
+    self.channel2.tick(cycles);
+    self.channel3.tick(cycles);
+    self.channel4.tick(cycles);
+  }
 
-      0xff20 => self.nr41 = w,
-      0xff21 => self.nr42 = w,
-      0xff22 => self.nr43 = w,
-      0xff23 => {
-        self.nr44 = w;
-        self.handle_channel_4_out();
-      }
+  fn clock_frame_sequencer(&mut self) {
+    if self.frame_sequencer_step & 1 == 0 {
+      self.channel1.clock_length();
+      self.channel2.clock_length();
+      self.channel3.clock_length();
+      self.channel4.clock_length();
+    }
 
-      0xff24 => self.nr50 = w,
-      0xff25 => self.nr51 = w,
-      0xff26 => self.nr52 = w,
-      0xff10...0xff3f => panic!("Unimplemented sound addr: 0x{:>04x}", addr),
-      _ => unimplemented!("Unsupported sound addr: 0x{:>04x}", addr),
-    };
+    if self.frame_sequencer_step == 2 || self.frame_sequencer_step == 6 {
+      self.channel1.clock_sweep();
+    }
+
+    if self.frame_sequencer_step == 7 {
+      self.channel1.clock_envelope();
+      self.channel2.clock_envelope();
+      self.channel4.clock_envelope();
+    }
+
+    self.frame_sequencer_step = (self.frame_sequencer_step + 1) & 0b111;
   }
 
-  pub fn read_word(&self, addr: u16) -> u8 {
-    unimplemented!("Unimplemented sound chip reg read at 0x{:>04x}", addr);
+  // Length counters enabled or triggered while the next step doesn't clock them get an extra clock.
+  fn is_length_clocked_next(&self) -> bool {
+    self.frame_sequencer_step & 1 == 0
   }
 
-  fn is_sound_reg_enabled(&self) -> bool {
-    bitn!(self.nr52, 7) == 0x1
+  // NR52: power (bit 7) and the read-only channel status bits (bits 0-3).
+  fn read_nr52(&self) -> u8 {
+    (self.is_powered as u8) << 7
+      | (self.channel4.is_enabled() as u8) << 3
+      | (self.channel3.is_enabled() as u8) << 2
+      | (self.channel2.is_enabled() as u8) << 1
+      | self.channel1.is_enabled() as u8
   }
 
-  fn handle_channel_1_out(&self) {
-    if self.muted {
-      return;
+  // Powering off clears the registers, powering on restarts the frame sequencer.
+  fn write_nr52(&mut self, w: u8) {
+    let is_powered = bitn!(w, 7) == 0x1;
+
+    if self.is_powered && !is_powered {
+      self.channel1.power_off();
+      self.channel2.power_off();
+      self.channel3.power_off();
+      self.channel4.power_off();
+      self.nr50 = 0;
+      self.nr51 = 0;
+    } else if !self.is_powered && is_powered {
+      self.frame_sequencer_timer = FRAME_SEQUENCER_PERIOD;
+      self.frame_sequencer_step = 0;
     }
 
-    if !self.is_sound_reg_enabled() {
-      return;
-    }
+    self.is_powered = is_powered;
+  }
 
-    if bitn!(self.nr14, 7) != 1 {
-      return;
+  // Passes the changes of the mix to the blip buffers.
+  fn output(&mut self, clock: u64) {
+    let (left, right) = self.mix();
+
+    if left != self.output.0 {
+      self.left.add_delta(clock, left - self.output.0);
+    }
+    if right != self.output.1 {
+      self.right.add_delta(clock, right - self.output.1);
     }
+    self.output = (left, right);
+  }
 
-    let wave_duty = self.nr11 >> 6;
-    let sound_length: f32 = (64 - (self.nr11 & 0b11_1111)) as f32 * (1f32 / 256f32);
-    let freq_raw: u32 = self.nr13 as u32 | (((self.nr14 & 0b111) as u32) << 8);
-    let freq: u32 = 131072 / (2048 - freq_raw);
-    let envelope: u8 = self.nr12 >> 4;
-    let is_envelope_inc: bool = bitn!(self.nr12, 3) == 1;
-    let envelope_steps: u8 = self.nr12 & 0b111;
-    let should_use_length: bool = bitn!(self.nr14, 6) == 1;
-
-    // @TODO: "When sound output is finished, bit 0 of register NR52, the Sound 1 ON flag, is reset."
-
-    // println!(
-    //   "WaveDuty 0b{:b} SoundLen {:?} Freq {:?} Envelope {:?} EnvelopeSteps {:?}",
-    //   wave_duty, sound_length, freq, envelope, envelope_steps
-    // );
-
-    {
-      let mut pocket = self.channel1_out.lock().unwrap();
-      *pocket = Some(SoundPacket::new(
-        freq as f32,
-        envelope as f32 / 15f32,
-        (44_100 * envelope_steps as usize) / 64,
-        true,
-        0.5,
-      ));
+  // Left and right outputs, -1.0 - 1.0. NR51 pans the channels, NR50 sets the master volume of the sides.
+  fn mix(&self) -> (f32, f32) {
+    if !self.is_powered {
+      return (0.0, 0.0);
     }
+
+    let dacs = [
+      dac(self.channel1.is_dac_enabled(), self.channel1.output()),
+      dac(self.channel2.is_dac_enabled(), self.channel2.output()),
+      dac(self.channel3.is_dac_enabled(), self.channel3.output()),
+      dac(self.channel4.is_dac_enabled(), self.channel4.output()),
+    ];
+
+    (
+      mix_side(&dacs, self.nr51 >> 4, self.nr50 >> 4),
+      mix_side(&dacs, self.nr51, self.nr50),
+    )
   }
+}
 
-  fn handle_channel_2_out(&self) {
-    // @TODO Implement.
+// Channels enabled in the panning bits 0-3, with the volume in bits 0-2.
+fn mix_side(dacs: &[f32; 4], panning: u8, volume: u8) -> f32 {
+  let mixed: f32 = dacs
+    .iter()
+    .enumerate()
+    .filter(|&(i, _)| bitn!(panning, i) == 0x1)
+    .map(|(_, &dac)| dac)
+    .sum();
+
+  mixed / 4.0 * ((volume & 0b111) + 1) as f32 / 8.0
+}
+
+// Capacitor charge kept per output sample.
+fn high_pass_charge(sample_rate: u32) -> f32 {
+  HIGH_PASS_CHARGE.powf(CPU_FREQUENCY as f64 / sample_rate as f64) as f32
+}
+
+// Converts the digital output (0 - 15) to -1.0 - 1.0, a disabled DAC outputs 0.
+fn dac(is_enabled: bool, output: u8) -> f32 {
+  if is_enabled {
+    output as f32 / 7.5 - 1.0
+  } else {
+    0.0
   }
+}
 
-  fn handle_channel_4_out(&self) {
-    // @TODO Implement.
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_read_masks() {
+    let mut sound = Sound::new();
+    sound.write_word(0xff26, 0x80).unwrap();
+    assert_eq!(0xf0, sound.read_word(0xff26).unwrap());
+
+    sound.write_word(0xff11, 0b1010_1010).unwrap();
+    assert_eq!(0b1011_1111, sound.read_word(0xff11).unwrap());
+    sound.write_word(0xff13, 0x12).unwrap();
+    assert_eq!(0xff, sound.read_word(0xff13).unwrap());
+    sound.write_word(0xff25, 0x5a).unwrap();
+    assert_eq!(0x5a, sound.read_word(0xff25).unwrap());
+    assert_eq!(0xff, sound.read_word(0xff27).unwrap());
+
+    // Triggering channel 1 sets its status bit.
+    sound.write_word(0xff12, 0xf0).unwrap();
+    sound.write_word(0xff14, 0x80).unwrap();
+    assert_eq!(0xf1, sound.read_word(0xff26).unwrap());
   }
 
-  pub fn mute(&mut self) {
-    self.muted = true;
+  #[test]
+  fn test_power_off() {
+    let mut sound = Sound::new();
+    sound.reset_post_boot();
+    sound.write_word(0xff30, 0x12).unwrap();
+
+    sound.write_word(0xff26, 0x00).unwrap();
+    assert_eq!(0x70, sound.read_word(0xff26).unwrap());
+    assert_eq!(0x3f, sound.read_word(0xff11).unwrap());
+    assert_eq!(0x00, sound.read_word(0xff24).unwrap());
+    assert_eq!(0x12, sound.read_word(0xff30).unwrap());
+
+    // Only the lengths and wave RAM are writable.
+    sound.write_word(0xff24, 0x77).unwrap();
+    sound.write_word(0xff11, 0xff).unwrap();
+    sound.write_word(0xff30, 0x34).unwrap();
+    assert_eq!(0x00, sound.read_word(0xff24).unwrap());
+    assert_eq!(0x3f, sound.read_word(0xff11).unwrap());
+    assert_eq!(0x34, sound.read_word(0xff30).unwrap());
+  }
+
+  #[test]
+  fn test_panning() {
+    let mut sound = Sound::new();
+    sound.reset_post_boot();
+    // Channel 1 at 512 Hz on the left only.
+    sound.write_word(0xff25, 0x10).unwrap();
+    sound.write_word(0xff13, 0x00).unwrap();
+    sound.write_word(0xff14, 0x87).unwrap();
+
+    sound.update(0, 70224);
+    let samples = sound.drain_samples();
+    assert!(samples.chunks(2).any(|sample| sample[0].abs() > 0.1));
+    assert!(samples.chunks(2).all(|sample| sample[1] == 0.0));
   }
 }
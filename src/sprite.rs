@@ -0,0 +1,120 @@
+// Hardware limit of the sprites displayed on one line.
+pub const SPRITES_PER_LINE: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sprite {
+  // Screen coordinates, OAM stores them offset by (8, 16).
+  pub x: i32,
+  pub y: i32,
+  pub tile: u8,
+  pub attributes: u8,
+  pub oam_index: usize,
+}
+
+impl Sprite {
+  pub fn from_oam(oam: &[u8], oam_index: usize) -> Sprite {
+    let offs = oam_index * 4;
+    Sprite {
+      y: oam[offs] as i32 - 16,
+      x: oam[offs + 1] as i32 - 8,
+      tile: oam[offs + 2],
+      attributes: oam[offs + 3],
+      oam_index,
+    }
+  }
+
+  // Background and window colors 1-3 are drawn over the sprite.
+  pub fn is_behind_background(&self) -> bool {
+    bitn!(self.attributes, 7) == 0x1
+  }
+
+  pub fn is_y_flipped(&self) -> bool {
+    bitn!(self.attributes, 6) == 0x1
+  }
+
+  pub fn is_x_flipped(&self) -> bool {
+    bitn!(self.attributes, 5) == 0x1
+  }
+
+  pub fn uses_obp1(&self) -> bool {
+    bitn!(self.attributes, 4) == 0x1
+  }
+
+  pub fn covers_line(&self, line: u8, height: u8) -> bool {
+    let line = line as i32;
+    line >= self.y && line < self.y + height as i32
+  }
+
+  // Address of the tile line (relative to 0x8000) to draw on the given screen line.
+  pub fn tile_line_addr(&self, line: u8, height: u8) -> usize {
+    let mut sprite_line = (line as i32 - self.y) as u8;
+    if self.is_y_flipped() {
+      sprite_line = height - 1 - sprite_line;
+    }
+
+    // 8x16 sprites ignore the lowest bit of the tile number.
+    let tile = if height == 16 {
+      self.tile & 0xfe
+    } else {
+      self.tile
+    };
+
+    tile as usize * 0x10 + ((sprite_line as usize) << 1)
+  }
+}
+
+// OAM scan (mode 2): the first 10 sprites in OAM order that overlap the line, X is not considered.
+// The result is ordered by drawing priority: lower X first, OAM order on equal X.
+pub fn scan_line(oam: &[u8], line: u8, height: u8) -> Vec<Sprite> {
+  let mut sprites: Vec<Sprite> = (0..40)
+    .map(|i| Sprite::from_oam(oam, i))
+    .filter(|sprite| sprite.covers_line(line, height))
+    .take(SPRITES_PER_LINE)
+    .collect();
+
+  sprites.sort_by_key(|sprite| (sprite.x, sprite.oam_index));
+  sprites
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn make_oam(sprites: &[(u8, u8, u8, u8)]) -> Vec<u8> {
+    let mut oam = vec![0; 0xa0];
+    for (i, &(y, x, tile, attributes)) in sprites.iter().enumerate() {
+      oam[i * 4..i * 4 + 4].copy_from_slice(&[y, x, tile, attributes]);
+    }
+    oam
+  }
+
+  #[test]
+  fn test_scan_limit() {
+    let oam = make_oam(&[(16, 8, 0, 0); 12]);
+    let sprites = scan_line(&oam, 0, 8);
+
+    assert_eq!(SPRITES_PER_LINE, sprites.len());
+    assert_eq!(9, sprites.last().unwrap().oam_index);
+    assert!(scan_line(&oam, 8, 8).is_empty());
+    assert_eq!(SPRITES_PER_LINE, scan_line(&oam, 15, 16).len());
+  }
+
+  #[test]
+  fn test_scan_priority() {
+    let oam = make_oam(&[(16, 30, 0, 0), (16, 20, 0, 0), (16, 30, 0, 0)]);
+    let order: Vec<usize> = scan_line(&oam, 0, 8)
+      .iter()
+      .map(|sprite| sprite.oam_index)
+      .collect();
+
+    assert_eq!(vec![1, 0, 2], order);
+  }
+
+  #[test]
+  fn test_tile_line_addr() {
+    let sprite = Sprite::from_oam(&make_oam(&[(16, 8, 0x03, 0b0100_0000)]), 0);
+    assert_eq!(0x30 + 14, sprite.tile_line_addr(0, 8));
+    // 8x16 uses the even tile for the upper half, flipped it starts with the last line of the odd tile.
+    assert_eq!(0x20 + 30, sprite.tile_line_addr(0, 16));
+  }
+}
@@ -0,0 +1,330 @@
+use super::channel::*;
+
+// Waveforms of the 4 duty settings (12.5%, 25%, 50%, 75%), played from bit 0.
+const DUTY_PATTERNS: [u8; 4] = [0b1000_0000, 0b1000_0001, 0b1110_0001, 0b0111_1110];
+
+const MAX_FREQUENCY: u16 = 2047;
+
+// Frequency sweep of channel 1 (NR10), clocked at 128 Hz.
+#[derive(Debug, Default)]
+pub struct Sweep {
+  reg: u8,
+  is_enabled: bool,
+  shadow_frequency: u16,
+  timer: u8,
+  // Calculated a frequency in negate mode since the last trigger.
+  has_negated: bool,
+}
+
+impl Sweep {
+  pub fn read(&self) -> u8 {
+    self.reg
+  }
+
+  // Returns false when leaving the negate mode after a negated calculation, which disables the channel.
+  pub fn write(&mut self, w: u8) -> bool {
+    self.reg = w;
+    !self.has_negated || self.is_negating()
+  }
+
+  // Returns false when the initial calculation overflows, which disables the channel.
+  pub fn trigger(&mut self, frequency: u16) -> bool {
+    self.shadow_frequency = frequency;
+    self.timer = self.timer_period();
+    self.is_enabled = self.period() != 0 || self.shift() != 0;
+    self.has_negated = false;
+
+    self.shift() == 0 || self.calculate() <= MAX_FREQUENCY
+  }
+
+  // Updates the frequency, returns false when it overflows, which disables the channel.
+  pub fn clock(&mut self, frequency: &mut u16) -> bool {
+    if self.timer > 1 {
+      self.timer -= 1;
+      return true;
+    }
+    self.timer = self.timer_period();
+
+    if !self.is_enabled || self.period() == 0 {
+      return true;
+    }
+
+    let new_frequency = self.calculate();
+    if new_frequency > MAX_FREQUENCY {
+      return false;
+    }
+    if self.shift() == 0 {
+      return true;
+    }
+
+    self.shadow_frequency = new_frequency;
+    *frequency = new_frequency;
+    // The new frequency is checked again for an overflow but not used.
+    self.calculate() <= MAX_FREQUENCY
+  }
+
+  fn calculate(&mut self) -> u16 {
+    let delta = self.shadow_frequency >> self.shift();
+    if self.is_negating() {
+      self.has_negated = true;
+      self.shadow_frequency - delta
+    } else {
+      self.shadow_frequency + delta
+    }
+  }
+
+  fn period(&self) -> u8 {
+    (self.reg >> 4) & 0b111
+  }
+
+  // The timer treats a period of 0 as 8.
+  fn timer_period(&self) -> u8 {
+    match self.period() {
+      0 => 8,
+      period => period,
+    }
+  }
+
+  fn is_negating(&self) -> bool {
+    bitn!(self.reg, 3) == 0x1
+  }
+
+  fn shift(&self) -> u8 {
+    self.reg & 0b111
+  }
+}
+
+// Pulse channel (1 and 2).
+#[derive(Debug)]
+pub struct SquareChannel {
+  is_enabled: bool,
+  duty: u8,
+  duty_step: u8,
+  // 11 bit value of NRx3 and NRx4.
+  frequency: u16,
+  // T-cycles until the next duty step.
+  timer: u32,
+  // Only channel 1 has one.
+  sweep: Option<Sweep>,
+  envelope: Envelope,
+  length: LengthCounter,
+}
+
+impl Default for SquareChannel {
+  fn default() -> SquareChannel {
+    SquareChannel::new()
+  }
+}
+
+impl SquareChannel {
+  pub fn new() -> SquareChannel {
+    SquareChannel {
+      is_enabled: false,
+      duty: 0,
+      duty_step: 0,
+      frequency: 0,
+      timer: period(0),
+      sweep: None,
+      envelope: Envelope::default(),
+      length: LengthCounter::new(64),
+    }
+  }
+
+  pub fn with_sweep() -> SquareChannel {
+    SquareChannel {
+      sweep: Some(Sweep::default()),
+      ..SquareChannel::new()
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.is_enabled
+  }
+
+  pub fn is_dac_enabled(&self) -> bool {
+    self.envelope.is_dac_enabled()
+  }
+
+  // Digital output, 0 - 15.
+  pub fn output(&self) -> u8 {
+    if !self.is_enabled {
+      return 0;
+    }
+
+    bitn!(DUTY_PATTERNS[self.duty as usize], self.duty_step) * self.envelope.volume()
+  }
+
+  // Clears the registers, the length counter keeps running on the DMG.
+  pub fn power_off(&mut self) {
+    self.length.set_enabled(false, true);
+
+    *self = SquareChannel {
+      sweep: self.sweep.as_ref().map(|_| Sweep::default()),
+      length: self.length,
+      ..SquareChannel::new()
+    };
+  }
+
+  pub fn read_nrx0(&self) -> u8 {
+    self.sweep.as_ref().map_or(0, |sweep| sweep.read())
+  }
+
+  pub fn read_nrx1(&self) -> u8 {
+    self.duty << 6
+  }
+
+  pub fn read_nrx2(&self) -> u8 {
+    self.envelope.read()
+  }
+
+  pub fn read_nrx4(&self) -> u8 {
+    (self.length.is_enabled() as u8) << 6
+  }
+
+  // NR10: sweep period (bits 4-6), negate (bit 3) and shift (bits 0-2).
+  pub fn write_nrx0(&mut self, w: u8) {
+    if let Some(sweep) = &mut self.sweep {
+      if !sweep.write(w) {
+        self.is_enabled = false;
+      }
+    }
+  }
+
+  // NRx1: duty (bits 6-7) and length (bits 0-5).
+  pub fn write_nrx1(&mut self, w: u8) {
+    self.duty = w >> 6;
+    self.length.load(w & 0b11_1111);
+  }
+
+  // NRx2: volume envelope.
+  pub fn write_nrx2(&mut self, w: u8) {
+    self.envelope.write(w);
+    if !self.is_dac_enabled() {
+      self.is_enabled = false;
+    }
+  }
+
+  // NRx3: frequency lower 8 bits.
+  pub fn write_nrx3(&mut self, w: u8) {
+    self.frequency = (self.frequency & 0x700) | w as u16;
+  }
+
+  // NRx4: trigger (bit 7), length enable (bit 6) and frequency upper 3 bits.
+  pub fn write_nrx4(&mut self, w: u8, is_length_clocked_next: bool) {
+    self.frequency = (self.frequency & 0xff) | ((w as u16 & 0b111) << 8);
+    if self
+      .length
+      .set_enabled(bitn!(w, 6) == 0x1, is_length_clocked_next)
+    {
+      self.is_enabled = false;
+    }
+
+    if bitn!(w, 7) == 0x1 {
+      self.trigger(is_length_clocked_next);
+    }
+  }
+
+  pub fn tick(&mut self, cycles: u32) {
+    let mut cycles = cycles;
+    while cycles >= self.timer {
+      cycles -= self.timer;
+      self.timer = period(self.frequency);
+      self.duty_step = (self.duty_step + 1) & 0b111;
+    }
+    self.timer -= cycles;
+  }
+
+  pub fn clock_length(&mut self) {
+    if self.length.clock() {
+      self.is_enabled = false;
+    }
+  }
+
+  pub fn clock_envelope(&mut self) {
+    self.envelope.clock();
+  }
+
+  pub fn clock_sweep(&mut self) {
+    if let Some(sweep) = &mut self.sweep {
+      if !sweep.clock(&mut self.frequency) {
+        self.is_enabled = false;
+      }
+    }
+  }
+
+  fn trigger(&mut self, is_length_clocked_next: bool) {
+    self.is_enabled = self.is_dac_enabled();
+    self.timer = period(self.frequency);
+    self.envelope.trigger();
+    self.length.trigger(is_length_clocked_next);
+
+    if let Some(sweep) = &mut self.sweep {
+      if !sweep.trigger(self.frequency) {
+        self.is_enabled = false;
+      }
+    }
+  }
+}
+
+// T-cycles of one duty step.
+fn period(frequency: u16) -> u32 {
+  (2048 - frequency as u32) * 4
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_duty() {
+    let mut channel = SquareChannel::new();
+    // 25% duty, full volume.
+    channel.write_nrx1(0b0100_0000);
+    channel.write_nrx2(0xf0);
+    channel.write_nrx3(0xff);
+    channel.write_nrx4(0b1000_0111, true);
+
+    let mut waveform = vec![];
+    for _ in 0..8 {
+      waveform.push(channel.output());
+      channel.tick(period(0x7ff));
+    }
+    assert_eq!(vec![15, 0, 0, 0, 0, 0, 0, 15], waveform);
+  }
+
+  #[test]
+  fn test_sweep() {
+    let mut channel = SquareChannel::with_sweep();
+    // Period 1, shift 1.
+    channel.write_nrx0(0b0001_0001);
+    channel.write_nrx2(0xf0);
+    channel.write_nrx3(0x00);
+    channel.write_nrx4(0b1000_0010, true);
+    assert!(channel.is_enabled());
+
+    // 0x200 -> 0x300 -> 0x480 -> 0x6c0, with 0xa20 failing the second overflow check.
+    channel.clock_sweep();
+    assert_eq!(0x300, channel.frequency);
+    channel.clock_sweep();
+    assert_eq!(0x480, channel.frequency);
+    assert!(channel.is_enabled());
+    channel.clock_sweep();
+    assert_eq!(0x6c0, channel.frequency);
+    assert!(!channel.is_enabled());
+  }
+
+  #[test]
+  fn test_sweep_negate_quirk() {
+    let mut channel = SquareChannel::with_sweep();
+    // Period 1, negate, shift 1.
+    channel.write_nrx0(0b0001_1001);
+    channel.write_nrx2(0xf0);
+    channel.write_nrx4(0b1000_0100, true);
+    channel.clock_sweep();
+    assert_eq!(0x200, channel.frequency);
+
+    // Leaving the negate mode after a negated calculation disables the channel.
+    channel.write_nrx0(0b0001_0001);
+    assert!(!channel.is_enabled());
+  }
+}
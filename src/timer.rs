@@ -1,10 +1,10 @@
+use super::mem::UnmappedAddress;
+
 // 4'194'304 Hz (4194304)
 // Div 4 = 1048576 (machine cycles per sec)
 
 // DIV: 16384
 
-use super::util::*;
-
 enum InputClockSpeed {
   Hz4096,
   Hz262144,
@@ -19,62 +19,142 @@ pub struct TimerResult {
 
 #[derive(Debug, Default)]
 pub struct Timer {
-  div: u8,
+  // Internal 16 bit counter incremented every t-cycle, DIV is the upper 8 bits.
+  divider: u16,
   tima: u8,
   tma: u8,
   tac: u8,
 }
 
 impl Timer {
-  pub fn reset(&mut self) {}
+  pub fn reset(&mut self) {
+    *self = Timer::default();
+  }
+
+  // State left behind by the DMG boot ROM.
+  pub fn reset_post_boot(&mut self) {
+    self.reset();
+    self.divider = 0xabcc;
+  }
 
   pub fn update(&mut self, cycles_prev: u64, cycles: u64) -> TimerResult {
     let mut result: TimerResult = TimerResult::default();
 
-    // "This register is incremented at rate of 16384H." -> 4194304 / 16384 = 256.
-    if Util::did_tick_happened(cycles_prev, cycles, 256) {
-      self.div.wrapping_add(1);
-    }
+    // The timer is driven by the divider, TIMA ticks on the falling edge of the selected divider bit.
+    for _ in 0..(cycles - cycles_prev) {
+      let divider_prev = self.divider;
+      self.divider = self.divider.wrapping_add(1);
 
-    if self.timer_enabled() {
-      if match self.input_clock_select() {
-        InputClockSpeed::Hz4096 => Util::did_tick_happened(cycles_prev, cycles, 1024),
-        InputClockSpeed::Hz262144 => Util::did_tick_happened(cycles_prev, cycles, 16),
-        InputClockSpeed::Hz65536 => Util::did_tick_happened(cycles_prev, cycles, 64),
-        InputClockSpeed::Hz16384 => Util::did_tick_happened(cycles_prev, cycles, 256),
-      } {
-        if self.tima == 0xff {
-          self.tima = self.tma;
-          result.interrupt_generated = true;
-        } else {
-          self.tima.wrapping_add(0x1);
-        }
+      if self.did_timer_bit_fall(divider_prev, self.divider) && self.tick_tima() {
+        result.interrupt_generated = true;
       }
     }
 
     result
   }
 
-  pub fn write_word(&mut self, addr: u16, w: u8) {
+  pub fn read_word(&self, addr: u16) -> Result<u8, UnmappedAddress> {
+    match addr {
+      0xff04 => Ok((self.divider >> 8) as u8),
+      0xff05 => Ok(self.tima),
+      0xff06 => Ok(self.tma),
+      // Unused upper bits always read as 1.
+      0xff07 => Ok(self.tac | 0b1111_1000),
+      _ => Err(UnmappedAddress(addr)),
+    }
+  }
+
+  pub fn write_word(&mut self, addr: u16, w: u8) -> Result<(), UnmappedAddress> {
     match addr {
-      0xff04 => self.div = 0x0,
+      0xff04 => self.reset_divider(),
+      0xff05 => self.tima = w,
       0xff06 => self.tma = w,
       0xff07 => self.tac = w & 0b111,
-      _ => unimplemented!("Timer reg write is not yet implemented on 0x{:>04x}", addr),
+      _ => return Err(UnmappedAddress(addr)),
+    }
+    Ok(())
+  }
+
+  // Any write to DIV (or STOP) resets it.
+  pub fn reset_divider(&mut self) {
+    // Resetting the divider can cause a falling edge on the selected bit, which ticks TIMA.
+    if self.did_timer_bit_fall(self.divider, 0x0) {
+      self.tick_tima();
+    }
+    self.divider = 0x0;
+  }
+
+  // Returns true on overflow.
+  fn tick_tima(&mut self) -> bool {
+    if self.tima == 0xff {
+      self.tima = self.tma;
+      true
+    } else {
+      self.tima += 1;
+      false
     }
   }
 
+  fn did_timer_bit_fall(&self, divider_prev: u16, divider: u16) -> bool {
+    if !self.timer_enabled() {
+      return false;
+    }
+
+    let bit = match self.input_clock_select() {
+      InputClockSpeed::Hz4096 => 9,
+      InputClockSpeed::Hz262144 => 3,
+      InputClockSpeed::Hz65536 => 5,
+      InputClockSpeed::Hz16384 => 7,
+    };
+
+    bitn!(divider_prev, bit) == 0x1 && bitn!(divider, bit) == 0x0
+  }
+
   fn timer_enabled(&self) -> bool {
     bitn!(self.tac, 2) == 0x1
   }
 
   fn input_clock_select(&self) -> InputClockSpeed {
-    match self.tac & 0x11 {
-      0x00 => InputClockSpeed::Hz4096,
-      0x01 => InputClockSpeed::Hz262144,
-      0x10 => InputClockSpeed::Hz65536,
-      0x11 => InputClockSpeed::Hz16384,
-      bits @ _ => panic!("Invalid input clock selector bits: 0b{:b}", bits),
+    match self.tac & 0b11 {
+      0b00 => InputClockSpeed::Hz4096,
+      0b01 => InputClockSpeed::Hz262144,
+      0b10 => InputClockSpeed::Hz65536,
+      // 0b11
+      _ => InputClockSpeed::Hz16384,
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_div() {
+    let mut timer = Timer::default();
+    timer.reset_post_boot();
+    assert_eq!(0xab, timer.read_word(0xff04).unwrap());
+
+    timer.update(0, 0x34);
+    assert_eq!(0xac, timer.read_word(0xff04).unwrap());
+
+    timer.write_word(0xff04, 0x12).unwrap();
+    assert_eq!(0x00, timer.read_word(0xff04).unwrap());
+    assert_eq!(0xf8, timer.read_word(0xff07).unwrap());
+  }
+
+  #[test]
+  fn test_tima_overflow() {
+    let mut timer = Timer::default();
+    timer.write_word(0xff06, 0x42).unwrap();
+    timer.write_word(0xff05, 0xfe).unwrap();
+    // Enabled, 262144 Hz: 16 t-cycles per tick.
+    timer.write_word(0xff07, 0b101).unwrap();
+
+    assert!(!timer.update(0, 16).interrupt_generated);
+    assert_eq!(0xff, timer.read_word(0xff05).unwrap());
+
+    assert!(timer.update(16, 32).interrupt_generated);
+    assert_eq!(0x42, timer.read_word(0xff05).unwrap());
+  }
+}
@@ -41,6 +41,22 @@ impl Util {
     w < acc
   }
 
+  pub fn has_half_carry_with_carry(w: u8, acc: u8, carry: u8) -> bool {
+    (w & 0xf) + (acc & 0xf) + carry > 0xf
+  }
+
+  pub fn has_carry_with_carry(w: u8, acc: u8, carry: u8) -> bool {
+    (w as u16) + (acc as u16) + (carry as u16) > 0xff
+  }
+
+  pub fn has_half_borrow_with_carry(w: u8, acc: u8, carry: u8) -> bool {
+    (w & 0xf) < (acc & 0xf) + carry
+  }
+
+  pub fn has_borrow_with_carry(w: u8, acc: u8, carry: u8) -> bool {
+    (w as u16) < (acc as u16) + (carry as u16)
+  }
+
   pub fn swap(w: u8) -> u8 {
     (w << 4) | (w >> 4)
   }
@@ -49,7 +65,7 @@ impl Util {
     if sw >= 0 {
       dword.wrapping_add(sw as u16)
     } else {
-      dword.wrapping_sub(sw.abs() as u16)
+      dword.wrapping_sub(sw.unsigned_abs() as u16)
     }
   }
 
@@ -190,4 +206,24 @@ mod test {
 
     assert!(!Util::has_borrow(0x3e, 0xf));
   }
+
+  #[test]
+  fn test_has_carry_with_carry() {
+    assert!(Util::has_carry_with_carry(0b1111_1111, 0b0, 0b1));
+    assert!(Util::has_carry_with_carry(0b0000_0001, 0b1111_1111, 0b0));
+    assert!(Util::has_half_carry_with_carry(0b0000_0000, 0b1111, 0b1));
+
+    assert!(!Util::has_carry_with_carry(0b1111_1110, 0b0, 0b1));
+    assert!(!Util::has_half_carry_with_carry(0b0000_0000, 0b1110, 0b1));
+  }
+
+  #[test]
+  fn test_has_borrow_with_carry() {
+    assert!(Util::has_borrow_with_carry(0x00, 0x00, 0b1));
+    assert!(Util::has_borrow_with_carry(0x3e, 0x3e, 0b1));
+    assert!(Util::has_half_borrow_with_carry(0x10, 0x00, 0b1));
+
+    assert!(!Util::has_borrow_with_carry(0x3e, 0x3d, 0b1));
+    assert!(!Util::has_half_borrow_with_carry(0x11, 0x00, 0b1));
+  }
 }
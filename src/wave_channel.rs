@@ -0,0 +1,228 @@
+use super::channel::*;
+
+// Channel 3, plays the 32 4-bit samples of the wave RAM (0xff30 - 0xff3f).
+#[derive(Debug)]
+pub struct WaveChannel {
+  is_enabled: bool,
+  is_dac_enabled: bool,
+  // NR32 bits 5-6: mute, 100%, 50%, 25%.
+  volume_code: u8,
+  frequency: u16,
+  // T-cycles until the next sample.
+  timer: u32,
+  position: u8,
+  sample: u8,
+  // The wave RAM is only accessible while playing right when the channel reads it.
+  cycles_since_read: u32,
+  length: LengthCounter,
+  wave_ram: [u8; 16],
+}
+
+impl Default for WaveChannel {
+  fn default() -> WaveChannel {
+    WaveChannel::new()
+  }
+}
+
+impl WaveChannel {
+  pub fn new() -> WaveChannel {
+    WaveChannel {
+      is_enabled: false,
+      is_dac_enabled: false,
+      volume_code: 0,
+      frequency: 0,
+      timer: period(0),
+      position: 0,
+      sample: 0,
+      cycles_since_read: 0,
+      length: LengthCounter::new(256),
+      wave_ram: [0; 16],
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.is_enabled
+  }
+
+  pub fn is_dac_enabled(&self) -> bool {
+    self.is_dac_enabled
+  }
+
+  // Digital output, 0 - 15.
+  pub fn output(&self) -> u8 {
+    if !self.is_enabled || self.volume_code == 0 {
+      return 0;
+    }
+
+    self.sample >> (self.volume_code - 1)
+  }
+
+  // Clears the registers, the length counter and wave RAM are kept on the DMG.
+  pub fn power_off(&mut self) {
+    self.length.set_enabled(false, true);
+
+    *self = WaveChannel {
+      length: self.length,
+      wave_ram: self.wave_ram,
+      ..WaveChannel::new()
+    };
+  }
+
+  pub fn read_nr30(&self) -> u8 {
+    (self.is_dac_enabled as u8) << 7
+  }
+
+  pub fn read_nr32(&self) -> u8 {
+    self.volume_code << 5
+  }
+
+  pub fn read_nr34(&self) -> u8 {
+    (self.length.is_enabled() as u8) << 6
+  }
+
+  // NR30: DAC power (bit 7).
+  pub fn write_nr30(&mut self, w: u8) {
+    self.is_dac_enabled = bitn!(w, 7) == 0x1;
+    if !self.is_dac_enabled {
+      self.is_enabled = false;
+    }
+  }
+
+  // NR31: length.
+  pub fn write_nr31(&mut self, w: u8) {
+    self.length.load(w);
+  }
+
+  // NR32: output level (bits 5-6).
+  pub fn write_nr32(&mut self, w: u8) {
+    self.volume_code = (w >> 5) & 0b11;
+  }
+
+  // NR33: frequency lower 8 bits.
+  pub fn write_nr33(&mut self, w: u8) {
+    self.frequency = (self.frequency & 0x700) | w as u16;
+  }
+
+  // NR34: trigger (bit 7), length enable (bit 6) and frequency upper 3 bits.
+  pub fn write_nr34(&mut self, w: u8, is_length_clocked_next: bool) {
+    self.frequency = (self.frequency & 0xff) | ((w as u16 & 0b111) << 8);
+    if self
+      .length
+      .set_enabled(bitn!(w, 6) == 0x1, is_length_clocked_next)
+    {
+      self.is_enabled = false;
+    }
+
+    if bitn!(w, 7) == 0x1 {
+      self.trigger(is_length_clocked_next);
+    }
+  }
+
+  // Reads 0xff while playing, unless the channel is reading the wave RAM at the same time.
+  pub fn read_wave_ram(&self, index: usize) -> u8 {
+    match self.wave_ram_index(index) {
+      Some(index) => self.wave_ram[index],
+      None => 0xff,
+    }
+  }
+
+  // Ignored while playing, unless the channel is reading the wave RAM at the same time.
+  pub fn write_wave_ram(&mut self, index: usize, w: u8) {
+    if let Some(index) = self.wave_ram_index(index) {
+      self.wave_ram[index] = w;
+    }
+  }
+
+  pub fn tick(&mut self, cycles: u32) {
+    let mut cycles = cycles;
+    while cycles >= self.timer {
+      cycles -= self.timer;
+      self.timer = period(self.frequency);
+      self.position = (self.position + 1) & 0b1_1111;
+
+      // High nibble first.
+      let byte = self.wave_ram[self.position as usize >> 1];
+      self.sample = if self.position & 0b1 == 0 {
+        byte >> 4
+      } else {
+        byte & 0xf
+      };
+      self.cycles_since_read = 0;
+    }
+    self.timer -= cycles;
+    self.cycles_since_read = self.cycles_since_read.saturating_add(cycles);
+  }
+
+  pub fn clock_length(&mut self) {
+    if self.length.clock() {
+      self.is_enabled = false;
+    }
+  }
+
+  fn trigger(&mut self, is_length_clocked_next: bool) {
+    self.is_enabled = self.is_dac_enabled;
+    self.timer = period(self.frequency);
+    self.position = 0;
+    self.cycles_since_read = u32::MAX;
+    self.length.trigger(is_length_clocked_next);
+  }
+
+  // While playing, accesses go to the byte being played instead, if it was just read.
+  fn wave_ram_index(&self, index: usize) -> Option<usize> {
+    if !self.is_enabled {
+      Some(index)
+    } else if self.cycles_since_read < 2 {
+      Some(self.position as usize >> 1)
+    } else {
+      None
+    }
+  }
+}
+
+// T-cycles of one sample.
+fn period(frequency: u16) -> u32 {
+  (2048 - frequency as u32) * 2
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_wave_output() {
+    let mut channel = WaveChannel::new();
+    channel.write_wave_ram(0, 0x8f);
+    channel.write_nr30(0x80);
+    // 50% volume.
+    channel.write_nr32(0b0100_0000);
+    channel.write_nr33(0xff);
+    channel.write_nr34(0b1000_0111, true);
+
+    channel.tick(period(0x7ff));
+    assert_eq!(0xf >> 1, channel.output());
+    // Samples 1 - 31 are played before the first one again.
+    channel.tick(period(0x7ff) * 31);
+    assert_eq!(0x8 >> 1, channel.output());
+  }
+
+  #[test]
+  fn test_wave_ram_access_while_playing() {
+    let mut channel = WaveChannel::new();
+    channel.write_wave_ram(1, 0x12);
+    channel.write_nr30(0x80);
+    channel.write_nr33(0x00);
+    channel.write_nr34(0b1000_0111, true);
+    assert_eq!(0xff, channel.read_wave_ram(1));
+
+    // Sample 2 is in the second byte, accessible right after the channel read it.
+    channel.tick(period(0x700) * 2);
+    assert_eq!(0x12, channel.read_wave_ram(0));
+    channel.write_wave_ram(0, 0x34);
+    channel.tick(2);
+    assert_eq!(0xff, channel.read_wave_ram(1));
+    channel.write_wave_ram(1, 0x56);
+
+    channel.write_nr30(0x00);
+    assert_eq!(0x34, channel.read_wave_ram(1));
+  }
+}